@@ -1,12 +1,52 @@
+// pyo3 0.21's macro expansion calls unsafe helpers without inner `unsafe`
+// blocks, which edition 2024 flags on every #[pymethods] argument.
+#![allow(unsafe_op_in_unsafe_fn)]
+
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
-use std::collections::HashMap;
+use pyo3::types::{IntoPyDict, PyDict, PyList, PyTuple};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+pub type Money = i64; // paise, the smallest unit; anything finer is rounded per RoundingMode
+pub type Qty = i64; // thousandths of a share, see QTY_SCALE
+
+/// Quantities are fixed-point with 3 decimals (0.001 share) so fractional sizing keeps
+/// integer math. Order/position APIs taking plain `i64` counts are in whole shares.
+/// Paise values of fractional quantities truncate toward zero.
+pub const QTY_SCALE: Qty = 1000;
+
+fn shares(whole: i64) -> Qty {
+    whole.saturating_mul(QTY_SCALE)
+}
+
+fn shares_f64(qty: f64) -> Qty {
+    (qty * QTY_SCALE as f64).round() as Qty
+}
+
+fn qty_to_f64(qty: Qty) -> f64 {
+    qty as f64 / QTY_SCALE as f64
+}
+
+/// Exact decimal share count, e.g. "10" or "-0.5".
+fn format_qty(qty: Qty) -> String {
+    let sign = if qty < 0 { "-" } else { "" };
+    let (whole, frac) = (qty.abs() / QTY_SCALE, qty.abs() % QTY_SCALE);
+    if frac == 0 {
+        format!("{sign}{whole}")
+    } else {
+        format!("{sign}{whole}.{}", format!("{frac:03}").trim_end_matches('0'))
+    }
+}
 
-pub type Money = i64; // paise
-pub type Qty = i64;
+/// Paise value of `qty` at `px` per share.
+fn value_of(px: Money, qty: Qty) -> Money {
+    px.saturating_mul(qty) / QTY_SCALE
+}
 
-#[derive(Clone)]
+#[derive(Clone, FromPyObject)]
+#[pyo3(from_item_all)]
 struct Bar {
-    date: String, // "YYYY-MM-DD"
+    date: String, // "YYYY-MM-DD", or an RFC3339 timestamp "YYYY-MM-DDTHH:MM:SS..." for intraday bars
     symbol: String,
     open: Money,
     high: Money,
@@ -15,7 +55,8 @@ struct Bar {
     volume: i64,
 }
 
-#[derive(Clone)]
+#[derive(Clone, FromPyObject)]
+#[pyo3(from_item_all)]
 struct Fill {
     date: String,
     symbol: String,
@@ -24,66 +65,698 @@ struct Fill {
     price: Money,
     fee: Money,
     order_id: i64,
+    limit: Option<Money>, // limit price for limit orders, None for market
+    leg: Option<String>,  // bracket exit leg that filled, None otherwise
+    slippage: Money,      // (slipped price - unslipped price) * qty, signed so a cost is positive
+}
+
+impl Fill {
+    fn row(&self) -> FillRow {
+        (self.date.clone(), self.symbol.clone(), self.side.clone(), qty_to_f64(self.qty), self.price, self.fee, self.order_id, self.limit, self.leg.clone(), self.slippage)
+    }
+}
+
+/// Which price an order fills at. NextOpen: open of the first bar processed after the order is
+/// placed. SameClose: close of the bar the signal was generated on (fills happen in `end_of_day`).
+/// NextClose: close of the first bar processed after the order is placed.
+#[pyclass]
+#[derive(Clone, Copy, PartialEq)]
+enum FillTiming {
+    NextOpen,
+    SameClose,
+    NextClose,
+}
+
+impl FillTiming {
+    fn name(self) -> &'static str {
+        match self {
+            FillTiming::NextOpen => "NEXT_OPEN",
+            FillTiming::SameClose => "SAME_CLOSE",
+            FillTiming::NextClose => "NEXT_CLOSE",
+        }
+    }
+
+    fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "NEXT_OPEN" => Ok(FillTiming::NextOpen),
+            "SAME_CLOSE" => Ok(FillTiming::SameClose),
+            "NEXT_CLOSE" => Ok(FillTiming::NextClose),
+            _ => Err(PyValueError::new_err(format!("unknown fill timing {name}"))),
+        }
+    }
+}
+
+/// Order in which pending orders are tried each pass, which decides who gets the cash when
+/// `reject_on_insufficient_cash` is on. FifoByOrderId: oldest order first. SellsFirst: all
+/// SELLs, then all BUYs, each oldest first, so sale proceeds can fund same-day buys.
+#[pyclass]
+#[derive(Clone, Copy, PartialEq)]
+enum FillPriority {
+    FifoByOrderId,
+    SellsFirst,
+}
+
+impl FillPriority {
+    fn name(self) -> &'static str {
+        match self {
+            FillPriority::FifoByOrderId => "FIFO_BY_ORDER_ID",
+            FillPriority::SellsFirst => "SELLS_FIRST",
+        }
+    }
+
+    fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "FIFO_BY_ORDER_ID" => Ok(FillPriority::FifoByOrderId),
+            "SELLS_FIRST" => Ok(FillPriority::SellsFirst),
+            _ => Err(PyValueError::new_err(format!("unknown fill priority {name}"))),
+        }
+    }
+}
+
+/// Which shares a reducing fill closes. Average: realize against the blended avg cost.
+/// Fifo: against the oldest open lots first. Lifo: against the newest first.
+#[pyclass]
+#[derive(Clone, Copy, PartialEq)]
+enum CostBasis {
+    Average,
+    Fifo,
+    Lifo,
+}
+
+impl CostBasis {
+    fn name(self) -> &'static str {
+        match self {
+            CostBasis::Average => "AVERAGE",
+            CostBasis::Fifo => "FIFO",
+            CostBasis::Lifo => "LIFO",
+        }
+    }
+
+    fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "AVERAGE" => Ok(CostBasis::Average),
+            "FIFO" => Ok(CostBasis::Fifo),
+            "LIFO" => Ok(CostBasis::Lifo),
+            _ => Err(PyValueError::new_err(format!("unknown cost basis {name}"))),
+        }
+    }
+}
+
+/// What `on_bar` does with a second bar for a (symbol, date) it already has. Overwrite: the
+/// later bar replaces the earlier. Reject: raise ValueError. Merge: combine them as one bar
+/// (first open, highest high, lowest low, last close, summed volume).
+/// Overwrite and Merge count every duplicate in `duplicate_bar_count`.
+#[pyclass]
+#[derive(Clone, Copy, PartialEq)]
+enum DuplicateBars {
+    Overwrite,
+    Reject,
+    Merge,
+}
+
+impl DuplicateBars {
+    fn name(self) -> &'static str {
+        match self {
+            DuplicateBars::Overwrite => "OVERWRITE",
+            DuplicateBars::Reject => "REJECT",
+            DuplicateBars::Merge => "MERGE",
+        }
+    }
+
+    fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "OVERWRITE" => Ok(DuplicateBars::Overwrite),
+            "REJECT" => Ok(DuplicateBars::Reject),
+            "MERGE" => Ok(DuplicateBars::Merge),
+            _ => Err(PyValueError::new_err(format!("unknown duplicate bar policy {name}"))),
+        }
+    }
+}
+
+/// How divisions that land between two paise are resolved, for fees and avg cost.
+/// Truncate: toward zero. Round: to the nearest paisa, halves away from zero.
+/// Ceil: up to the next paisa.
+#[pyclass]
+#[derive(Clone, Copy, PartialEq)]
+enum RoundingMode {
+    Truncate,
+    Round,
+    Ceil,
+}
+
+impl RoundingMode {
+    fn name(self) -> &'static str {
+        match self {
+            RoundingMode::Truncate => "TRUNCATE",
+            RoundingMode::Round => "ROUND",
+            RoundingMode::Ceil => "CEIL",
+        }
+    }
+
+    fn from_name(name: &str) -> PyResult<Self> {
+        match name {
+            "TRUNCATE" => Ok(RoundingMode::Truncate),
+            "ROUND" => Ok(RoundingMode::Round),
+            "CEIL" => Ok(RoundingMode::Ceil),
+            _ => Err(PyValueError::new_err(format!("unknown rounding mode {name}"))),
+        }
+    }
+
+    /// num / den (den > 0) rounded to an integer under this mode.
+    fn div(self, num: i64, den: i64) -> i64 {
+        let (q, r) = (num / den, num % den);
+        match self {
+            RoundingMode::Truncate => q,
+            RoundingMode::Round if 2 * r.abs() >= den => q + num.signum(),
+            RoundingMode::Round => q,
+            RoundingMode::Ceil => if r > 0 { q + 1 } else { q },
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum OrderType {
+    Market,
+    Limit(Money), // limit price
+    Stop(Money),  // trigger price
+    // Stop trailing the best price seen since placement by trail_bps; extreme is the highest
+    // price for a SELL (lowest for a BUY), None until the symbol has a price
+    TrailingStop { trail_bps: i64, extreme: Option<Money> },
+}
+
+impl OrderType {
+    fn name(self) -> &'static str {
+        match self {
+            OrderType::Market => "MARKET",
+            OrderType::Limit(_) => "LIMIT",
+            OrderType::Stop(_) => "STOP",
+            OrderType::TrailingStop { .. } => "TRAILING_STOP",
+        }
+    }
+
+    /// Limit or trigger price; for a trailing stop, the level implied by its current watermark.
+    fn level(self, side: &str) -> Option<Money> {
+        match self {
+            OrderType::Market => None,
+            OrderType::Limit(p) | OrderType::Stop(p) => Some(p),
+            OrderType::TrailingStop { trail_bps, extreme } => extreme.map(|e| match side {
+                "SELL" => e * (10_000 - trail_bps) / 10_000,
+                _ => e * (10_000 + trail_bps) / 10_000,
+            }),
+        }
+    }
+
+    /// The same order after a `num`:`den` split: prices restated, trailing distance kept.
+    fn split(self, num: i64, den: i64) -> Self {
+        match self {
+            OrderType::Market => OrderType::Market,
+            OrderType::Limit(p) => OrderType::Limit(split_price(p, num, den)),
+            OrderType::Stop(p) => OrderType::Stop(split_price(p, num, den)),
+            OrderType::TrailingStop { trail_bps, extreme } => {
+                OrderType::TrailingStop { trail_bps, extreme: extreme.map(|e| split_price(e, num, den)) }
+            }
+        }
+    }
+
+    /// Move a trailing stop's watermark to `px` if that's more favorable; it never moves back.
+    fn ratchet(&mut self, side: &str, px: Money) {
+        if let OrderType::TrailingStop { extreme, .. } = self {
+            *extreme = Some(match (*extreme, side) {
+                (None, _) => px,
+                (Some(e), "SELL") => e.max(px),
+                (Some(e), _) => e.min(px),
+            });
+        }
+    }
+}
+
+// Checkpointed as {"type": "MARKET" | "LIMIT" | "STOP" | "TRAILING_STOP", "price": limit,
+// trigger or trailing watermark (or null), "trail_bps": trailing distance or null}
+impl ToPyObject for OrderType {
+    fn to_object(&self, py: Python<'_>) -> PyObject {
+        let (price, trail) = match *self {
+            OrderType::Market => (None, None),
+            OrderType::Limit(p) | OrderType::Stop(p) => (Some(p), None),
+            OrderType::TrailingStop { trail_bps, extreme } => (extreme, Some(trail_bps)),
+        };
+        [("type", self.name().to_object(py)), ("price", price.to_object(py)), ("trail_bps", trail.to_object(py))]
+            .into_py_dict_bound(py).into()
+    }
+}
+
+impl<'py> FromPyObject<'py> for OrderType {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let kind: String = ob.get_item("type")?.extract()?;
+        let price: Option<Money> = ob.get_item("price")?.extract()?;
+        let trail: Option<i64> = ob.get_item("trail_bps")?.extract()?;
+        match (kind.as_str(), price, trail) {
+            ("MARKET", _, _) => Ok(OrderType::Market),
+            ("LIMIT", Some(p), _) => Ok(OrderType::Limit(p)),
+            ("STOP", Some(p), _) => Ok(OrderType::Stop(p)),
+            ("TRAILING_STOP", extreme, Some(trail_bps)) => Ok(OrderType::TrailingStop { trail_bps, extreme }),
+            _ => Err(PyValueError::new_err(format!("bad order type {kind}"))),
+        }
+    }
+}
+
+// Commission charged on each fill
+#[derive(Clone, Copy)]
+enum FeeModel {
+    Bps(i64),            // proportional to notional
+    FlatPerOrder(Money), // fixed amount per order, charged on its first fill
+    PerShare(Money),     // per share traded
+    Max(i64, Money),     // greater of bps of notional and a flat minimum
+}
+
+impl FeeModel {
+    /// Fee for a fill of `qty` shares worth `notional`; `bps_override` (a per-symbol rate)
+    /// replaces the bps of the Bps and Max models. `first_fill` is false for the later partial
+    /// fills of an order.
+    fn fee(self, notional: Money, qty: Qty, bps_override: Option<i64>, rounding: RoundingMode, first_fill: bool) -> Money {
+        match self {
+            FeeModel::Bps(bps) => fee_for(notional, bps_override.unwrap_or(bps), rounding),
+            FeeModel::FlatPerOrder(flat) => if first_fill { flat } else { 0 },
+            FeeModel::PerShare(per) => rounding.div(per.saturating_mul(qty.abs()), QTY_SCALE),
+            FeeModel::Max(bps, min_flat) => fee_for(notional, bps_override.unwrap_or(bps), rounding).max(min_flat),
+        }
+    }
+}
+
+// Passed to `Engine(fee_model=...)` and checkpointed as {"type": "BPS" | "FLAT_PER_ORDER" |
+// "PER_SHARE" | "MAX", "bps": rate for BPS/MAX, "amount": paise for the other three}
+impl ToPyObject for FeeModel {
+    fn to_object(&self, py: Python<'_>) -> PyObject {
+        let (kind, bps, amount) = match *self {
+            FeeModel::Bps(b) => ("BPS", Some(b), None),
+            FeeModel::FlatPerOrder(a) => ("FLAT_PER_ORDER", None, Some(a)),
+            FeeModel::PerShare(a) => ("PER_SHARE", None, Some(a)),
+            FeeModel::Max(b, a) => ("MAX", Some(b), Some(a)),
+        };
+        [("type", kind.to_object(py)), ("bps", bps.to_object(py)), ("amount", amount.to_object(py))]
+            .into_py_dict_bound(py).into()
+    }
+}
+
+impl<'py> FromPyObject<'py> for FeeModel {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let kind: String = ob.get_item("type")?.extract()?;
+        let field = |k: &str| -> PyResult<Option<i64>> {
+            match ob.get_item(k) {
+                Ok(v) => v.extract(),
+                Err(_) => Ok(None),
+            }
+        };
+        match (kind.as_str(), field("bps")?, field("amount")?) {
+            ("BPS", Some(b), _) => Ok(FeeModel::Bps(b)),
+            ("FLAT_PER_ORDER", _, Some(a)) => Ok(FeeModel::FlatPerOrder(a)),
+            ("PER_SHARE", _, Some(a)) => Ok(FeeModel::PerShare(a)),
+            ("MAX", Some(b), Some(a)) => Ok(FeeModel::Max(b, a)),
+            _ => Err(PyValueError::new_err(format!("bad fee model {kind}"))),
+        }
+    }
+}
+
+// Base slippage of market and stop fills, always in the worse direction
+#[derive(Clone, Copy)]
+enum SlippageModel {
+    Bps(i64),          // proportional to price
+    Fixed(Money),      // paise per share
+    Ticks(i64, Money), // a number of ticks of the given size, per share
+}
+
+impl SlippageModel {
+    /// The proportional part, which volume impact and gap slippage add to.
+    fn bps(self) -> i64 {
+        match self {
+            SlippageModel::Bps(bps) => bps,
+            _ => 0,
+        }
+    }
+
+    /// The absolute part, in paise per share.
+    fn per_share(self) -> Money {
+        match self {
+            SlippageModel::Bps(_) => 0,
+            SlippageModel::Fixed(amount) => amount,
+            SlippageModel::Ticks(count, tick_size) => count * tick_size,
+        }
+    }
+}
+
+// Passed to `Engine(slippage_model=...)` and checkpointed as {"type": "BPS" | "FIXED" | "TICKS",
+// "bps": rate for BPS, "amount": paise per share for FIXED or the tick size for TICKS,
+// "ticks": tick count for TICKS}
+impl ToPyObject for SlippageModel {
+    fn to_object(&self, py: Python<'_>) -> PyObject {
+        let (kind, bps, amount, ticks) = match *self {
+            SlippageModel::Bps(b) => ("BPS", Some(b), None, None),
+            SlippageModel::Fixed(a) => ("FIXED", None, Some(a), None),
+            SlippageModel::Ticks(n, a) => ("TICKS", None, Some(a), Some(n)),
+        };
+        [("type", kind.to_object(py)), ("bps", bps.to_object(py)), ("amount", amount.to_object(py)), ("ticks", ticks.to_object(py))]
+            .into_py_dict_bound(py).into()
+    }
+}
+
+impl<'py> FromPyObject<'py> for SlippageModel {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        let kind: String = ob.get_item("type")?.extract()?;
+        let field = |k: &str| -> PyResult<Option<i64>> {
+            match ob.get_item(k) {
+                Ok(v) => v.extract(),
+                Err(_) => Ok(None),
+            }
+        };
+        match (kind.as_str(), field("bps")?, field("amount")?, field("ticks")?) {
+            ("BPS", Some(b), _, _) => Ok(SlippageModel::Bps(b)),
+            ("FIXED", _, Some(a), _) => Ok(SlippageModel::Fixed(a)),
+            ("TICKS", _, Some(a), Some(n)) => Ok(SlippageModel::Ticks(n, a)),
+            _ => Err(PyValueError::new_err(format!("bad slippage model {kind}"))),
+        }
+    }
+}
+
+#[derive(Clone, FromPyObject)]
+#[pyo3(from_item_all)]
+struct PendingOrder {
+    id: i64,
+    symbol: String,
+    side: String, // "BUY"/"SELL"
+    qty: Qty,
+    kind: OrderType,
+    bracket: Option<Bracket>, // exits to attach once this entry fills
+    oco_with: Option<i64>,    // sibling cancelled when this order fills
+    leg: Option<String>,      // "STOP"/"TARGET" for bracket exits
+    tif: String,              // "GTC" or "DAY": expire after the first bar that didn't fill it
+    timing: Option<String>,   // "MOO"/"MOC" overriding fill_timing for this order
+    fees: Option<Money>,      // charged on its fills so far, None before the first fill
+}
+
+#[derive(Clone, Copy, FromPyObject)]
+#[pyo3(from_item_all)]
+struct Bracket {
+    stop: Money,
+    target: Money,
 }
 
+// Shares opened by one fill, kept per symbol under FIFO/LIFO cost basis
+#[derive(Clone, FromPyObject)]
+#[pyo3(from_item_all)]
+struct Lot {
+    qty: Qty, // signed like the position
+    price: Money,
+    date: String,
+}
+
+// One reducing fill against an open position, priced against its avg cost (or a single lot
+// under FIFO/LIFO cost basis).
+#[derive(Clone, FromPyObject)]
+#[pyo3(from_item_all)]
+struct ClosedTrade {
+    symbol: String,
+    entry_date: String, // when the position was opened from flat
+    exit_date: String,
+    qty: Qty, // signed size of the closed part: negative for a short
+    entry_price: Money, // avg cost at exit
+    exit_price: Money,
+    pnl: Money,
+    mae: Money, // worst mark against the trade while open, as paise on the closed qty
+    mfe: Money, // best mark in its favour, likewise
+    slippage: Money, // share of the slippage of the fills that opened and closed it
+    fees: Money, // share of their fees, negative for net rebates
+}
+
+// (date, symbol, open, high, low, close, volume) as taken by Engine::on_bars_batch
+type BarRow = (String, String, Money, Money, Money, Money, i64);
+
+// (order_id, symbol, side, qty, type, limit/trigger price) as returned by Engine::open_orders
+type OpenOrderRow = (i64, String, String, f64, String, Option<Money>);
+
+// (symbol, entry_date, exit_date, qty, entry_avg_price, exit_price, pnl_paise, holding_days, mae_paise, mfe_paise)
+type ClosedTradeRow = (String, String, String, f64, Money, Money, Money, i64, Money, Money);
+
+// (date, symbol, side, qty, price, fee, order_id, limit, leg, slippage) as returned by Engine::fills
+type FillRow = (String, String, String, f64, Money, Money, i64, Option<Money>, Option<String>, Money);
+
 #[pyclass]
 struct Metrics {
     #[pyo3(get)]
     realized_pnl_paise: Money,
     #[pyo3(get)]
-    fees_paise: Money,
+    fees_paise: Money, // commission charged; rebates are counted apart, so this never goes negative
+    #[pyo3(get)]
+    rebates_paise: Money, // negative fees credited, as a positive amount
+    // realized_pnl_paise is at the slipped fill prices and before fees. The split below counts
+    // only the costs of closed qty: the slippage and fees of a fill that opens a position are
+    // released pro rata as it closes, so both equal realized_pnl_paise while it is open.
+    // slippage_paise, fees_paise and rebates_paise count every fill, so once flat
+    // gross_realized_pnl_paise - slippage_paise - fees_paise + rebates_paise == net_realized_pnl_paise.
+    #[pyo3(get)]
+    slippage_paise: Money, // sum over fills of |slipped price - unslipped price| * qty
+    #[pyo3(get)]
+    gross_realized_pnl_paise: Money, // realized_pnl_paise plus the slippage of closed trades: before any cost
+    #[pyo3(get)]
+    net_realized_pnl_paise: Money, // realized_pnl_paise less the fees of closed trades, net of rebates: after every cost
+    #[pyo3(get)]
+    fees_pct_of_pnl: f64, // fees / |realized pnl|, which is before fees, as a percent; 0.0 with no realized pnl
+    #[pyo3(get)]
+    fees_pct_of_notional: f64, // fees / sum(|fill notional|), as a percent; 0.0 with no fills
+    #[pyo3(get)]
+    interest_paise: Money, // net cash interest, negative when borrow charges dominate
     #[pyo3(get)]
     trades_closed: i64,
+    // realized pnl, closed trades and winners split by the side of the position closed
+    #[pyo3(get)]
+    long_realized_pnl_paise: Money,
+    #[pyo3(get)]
+    short_realized_pnl_paise: Money,
+    #[pyo3(get)]
+    long_trades_closed: i64,
+    #[pyo3(get)]
+    short_trades_closed: i64,
+    #[pyo3(get)]
+    long_wins: i64,
+    #[pyo3(get)]
+    short_wins: i64,
+    #[pyo3(get)]
+    max_consecutive_wins: i64, // longest run of winning closed trades, in close order
+    #[pyo3(get)]
+    max_consecutive_losses: i64, // likewise for trades with pnl <= 0
     #[pyo3(get)]
     win_rate: f64,
     #[pyo3(get)]
+    profit_factor: f64, // gross profit / gross loss, 0.0 when there are no losing trades
+    #[pyo3(get)]
+    expectancy_paise: f64, // average pnl per closed trade
+    #[pyo3(get)]
     max_drawdown_paise: Money,
     #[pyo3(get)]
     max_drawdown_pct: f64,
     #[pyo3(get)]
+    max_drawdown_days: i64, // calendar days from peak to recovery, or to the last mark if still down
+    #[pyo3(get)]
     sharpe: f64,
     #[pyo3(get)]
-    annual_return_pct: f64,
+    sortino: f64, // 0.0 when no return falls below the MAR (Rf)
+    #[pyo3(get)]
+    omega: f64, // sum of returns' excess over omega_threshold_pct / sum of their shortfall; 0.0 without a shortfall
+    #[pyo3(get)]
+    annual_return_pct: f64, // exp(mean log return * periods_per_year) - 1, as a percent
+    #[pyo3(get)]
+    cagr_pct: f64, // (last / first equity mark, net of deposits and withdrawals)^(periods_per_year / periods) - 1, as a percent
     #[pyo3(get)]
     volatility: f64,
+    #[pyo3(get)]
+    calmar: f64, // annual_return_pct / max_drawdown_pct, 0.0 without a drawdown
+    #[pyo3(get)]
+    recovery_factor: f64, // net profit (last mark less starting equity and net deposits) / max_drawdown_paise, 0.0 without a drawdown
+    #[pyo3(get)]
+    avg_gross_exposure: f64, // mean over equity marks of sum(|position value|) / equity
+    #[pyo3(get)]
+    turnover_annual: f64, // sum(|fill notional|) / mean equity * periods_per_year / marks
+    #[pyo3(get)]
+    ulcer_index: f64, // RMS of the percent drawdown at every equity mark
+    #[pyo3(get)]
+    pain_ratio: f64, // annual_return_pct / ulcer_index, 0.0 without a drawdown
+    #[pyo3(get)]
+    beta: f64, // vs the benchmark; 0.0 without one
+    #[pyo3(get)]
+    alpha_annual_pct: f64,
+    #[pyo3(get)]
+    information_ratio: f64, // annualized mean / sd of strategy minus benchmark returns
+    #[pyo3(get)]
+    max_relative_drawdown_pct: f64, // deepest point of relative_drawdown_curve; 0.0 without a benchmark
+    #[pyo3(get)]
+    blown_up_date: Option<String>, // first mark with equity <= 0; see Engine::metrics
+    #[pyo3(get)]
+    is_valid: bool, // at least 2 per-period returns; otherwise the ratios and drawdowns are placeholder zeros
+}
+
+/// Dict conversion for checkpointing records; list every field of the struct.
+macro_rules! impl_to_dict {
+    ($ty:ty { $($field:ident),* $(,)? }) => {
+        impl ToPyObject for $ty {
+            fn to_object(&self, py: Python<'_>) -> PyObject {
+                let d = PyDict::new_bound(py);
+                $(d.set_item(stringify!($field), &self.$field).expect("str key into a fresh dict");)*
+                d.into()
+            }
+        }
+    };
+}
+
+impl_to_dict!(Bar { date, symbol, open, high, low, close, volume });
+impl_to_dict!(Fill { date, symbol, side, qty, price, fee, order_id, limit, leg, slippage });
+impl_to_dict!(ClosedTrade { symbol, entry_date, exit_date, qty, entry_price, exit_price, pnl, mae, mfe, slippage, fees });
+impl_to_dict!(Lot { qty, price, date });
+impl_to_dict!(PendingOrder { id, symbol, side, qty, kind, bracket, oco_with, leg, tif, timing, fees });
+impl_to_dict!(Bracket { stop, target });
+
+/// JSON has no tuples: rebuild each row list as a tuple before extracting it.
+fn extract_rows<'py, T: FromPyObject<'py>>(obj: &Bound<'py, PyAny>) -> PyResult<Vec<T>> {
+    obj.iter()?
+        .map(|row| PyTuple::new_bound(obj.py(), row?.downcast::<PyList>()?).extract())
+        .collect()
 }
 
 #[pyclass]
 struct Engine {
     starting_cash: Money,
+    starting_equity: Money, // starting cash plus initial positions at cost
     cash: Money,
-    fee_bps: i64,
-    slippage_bps: i64,
+    fee_model: FeeModel,
+    fee_bps_by_symbol: HashMap<String, i64>, // overrides the fee model's bps per symbol
+    fee_fn: Option<PyObject>, // Python fee callback replacing the fee model; not checkpointed
+    bar_fn: Option<PyObject>, // Python hook run after each fill pass and equity mark; not checkpointed
+    min_fee: Money, // floor on the total fee of each order
+    maker_fee_bps: Option<i64>, // replaces the fee model for limit fills; negative is a rebate
+    taker_fee_bps: Option<i64>, // replaces the fee model for every other fill
+    slippage_model: SlippageModel,
+    impact_bps: f64, // extra slippage at 100% of bar volume, scaled by participation
+    gap_slippage_coeff: f64, // extra slippage bps per bps of open gap beyond gap_threshold_bps
+    gap_threshold_bps: i64,
+    reject_on_insufficient_cash: bool,
+    reject_over_position_limit: bool, // otherwise truncate to the limit
+    allow_short: bool, // otherwise SELLs are clamped to the long position
+    max_position_qty: HashMap<String, Qty>, // cap on |position| per symbol
+    lot_sizes: HashMap<String, Qty>, // fills in `symbol` come in multiples of this
+    tick_sizes: HashMap<String, Money>, // fill prices in `symbol` are multiples of this, in its currency
+    max_notional_pct: Option<f64>, // cap on |position value| as % of equity, any symbol
+    warmup_until: Option<String>, // metrics ignore marks, trades and costs dated before this
+    max_participation_pct: Option<f64>, // max share of bar volume one order may fill
+    strict: bool, // raise on data problems instead of skipping
+    record_calls: bool, // log mutating calls for call_log / replay
+    fill_timing: FillTiming,
+    fill_priority: FillPriority,
+    annual_cash_rate_bps: i64,   // earned on positive cash
+    annual_borrow_rate_bps: i64, // charged on negative cash
+    periods_per_year: f64, // equity marks per year, for annualizing metrics and interest
+    calendar_aware: bool, // annualize metrics by the calendar span of the marks instead
+    trading_calendar: Option<Vec<String>>, // sorted valid end_of_period dates
+    fill_missing_days: bool, // carry the last mark forward over calendar dates that got none
+    risk_free_annual_pct: f64, // for sharpe, sortino and alpha
+    omega_threshold_pct: f64, // per-period return splitting gains from losses for omega
+    rounding: RoundingMode, // for fees and avg cost
+    duplicate_bars: DuplicateBars,
+    cost_basis: CostBasis,
+    leverage_limit: Option<f64>, // max gross exposure / equity before a margin call
+    halt_on_blowup: bool, // stop filling orders once equity has gone to zero or below
+    daily_loss_limit: Option<Money>, // reject new orders once equity is this far below the day's start
+    settlement_days: i64, // marks before sale proceeds can pay for buys
+    max_drawdown_stop_pct: Option<f64>, // drawdown at a mark that liquidates and stops trading
+    default_risk_pct: Option<f64>, // open risk of position qty no stop covers, % of its cost
 
     // state
     last_bar_by_symbol: HashMap<String, Bar>,
-    pending_orders: Vec<(i64, String, String, Qty)>, // (order_id, symbol, side, qty)
+    prev_close: HashMap<String, Money>, // close of the bar before last_bar_by_symbol's
+    bad_bars: i64, // bars dropped by on_bar validation
+    duplicate_bars_seen: i64, // repeated (symbol, date) bars overwritten or merged
+    bars_seen: i64, // distinct (symbol, date) bars accepted by on_bar
+    first_bar_date: String, // earliest and latest date of an accepted bar, "" before the first
+    last_bar_date: String,
+    pending_orders: Vec<PendingOrder>,
     next_order_id: i64,
+    rejected_orders: Vec<(i64, String, String)>, // (order_id, symbol, reason)
+    expired_orders: Vec<(i64, String, String)>,  // (order_id, symbol, date) of unfilled DAY orders
+    truncated_orders: Vec<(i64, String, String, f64)>, // (order_id, symbol, date, shares cut by position limits)
+    lot_adjustments: Vec<(i64, String, String, f64)>, // (order_id, symbol, date, odd-lot shares cut)
+    margin_calls: Vec<(String, Money, Money)>, // (date, gross exposure, equity) at marks over leverage_limit
+    unsettled: Vec<(i64, Money)>, // (marks left, amount) of sale proceeds not yet settled; already in cash
 
     positions: HashMap<String, Qty>,
-    avg_cost: HashMap<String, Money>, // avg cost per share in paise, long-only for MVP
+    entry_dates: HashMap<String, String>, // date each open position was opened
+    avg_cost: HashMap<String, Money>, // avg entry price per share in paise of the open position, long or short
+    basis: HashMap<String, Money>, // total cost in paise of each open position, long or short; unrounded by avg_cost
+    open_slippage: HashMap<String, Money>, // slippage paid opening each open position, not yet released to closed trades
+    open_fees: HashMap<String, Money>, // fees paid opening each open position, likewise; negative for net rebates
+    lots: HashMap<String, Vec<Lot>>, // open lots, oldest first; only kept under FIFO/LIFO
+    excursions: HashMap<String, (Money, Money)>, // (lowest, highest) price seen since each open position was opened
 
     fills: Vec<Fill>,
+    closed_trades: Vec<ClosedTrade>,
+    dividends: Vec<(String, String, Money)>, // (date, symbol, cash credited, negative when short)
+    cash_flows: Vec<(String, String, Money)>, // (date, category, delta), see post_cash
+    call_log: Vec<(String, PyObject, Option<String>)>, // (method, args tuple, error it raised) of each mutating call, when record_calls
+    call_log_start: Option<String>, // to_json of the engine before the first logged call
     equity_curve: Vec<(String, Money)>,
+    exposure_curve: Vec<(String, Money, Money)>, // (date, net, gross) position value at each mark
+    missing_days: Vec<String>, // calendar dates skipped between marks
+    mark_flows: Vec<Money>, // net deposits less withdrawals since the previous mark, one per equity_curve point
+    pending_flow: Money, // net deposits less withdrawals since the last mark
+    loss_day: String, // latest day seen by on_bar or process_fills_for_date, "" before any
+    loss_day_equity: Money, // equity when loss_day was first seen, plus capital moved since
+    benchmark: HashMap<String, Money>, // benchmark close by date, see set_benchmark
+    symbol_currency: HashMap<String, String>, // quote currency of symbols not in the base currency
+    fx_rates: HashMap<String, BTreeMap<String, i64>>, // currency -> date -> base per unit, in bps
 
     realized_pnl: Money,
     fees_paid: Money,
+    rebates: Money, // negative fees credited, as a positive number
+    slippage_paid: Money, // sum over fills of what slippage moved the price against us, times qty
+    interest: Money,
     trades_closed: i64,
     wins: i64,
+    gross_profit: Money, // sum of winning trade pnl
+    gross_loss: Money,   // sum of losing trade pnl, as a positive number
 
     peak_equity: Money,
+    peak_date: String, // date peak_equity was last reached, "" before the first mark
     max_dd: Money,
+    max_dd_days: i64, // longest recovered drawdown in calendar days
+    blown_up_date: Option<String>, // first mark with equity <= 0
+    drawdown_stop_date: Option<String>, // mark at which max_drawdown_stop_pct fired
+    exposure_sum: f64, // sum over marks of gross position value / equity
+}
+
+fn fee_for(notional: Money, fee_bps: i64, rounding: RoundingMode) -> Money {
+    // fee = notional * bps / 10000, to whole paise
+    rounding.div(notional * fee_bps, 10_000)
+}
+
+/// Order arguments shared by the place_* methods: direction is `side`, so qty must be positive.
+fn check_order(side: &str, qty: Qty, tif: &str) -> PyResult<()> {
+    check_side(side)?;
+    if qty <= 0 {
+        return Err(PyValueError::new_err(format!("qty must be positive, got {}", format_qty(qty))));
+    }
+    if tif != "GTC" && tif != "DAY" {
+        return Err(PyValueError::new_err(format!("tif must be GTC or DAY, got {tif}")));
+    }
+    Ok(())
 }
 
-fn fee_for(notional: Money, fee_bps: i64) -> Money {
-    // fee = notional * bps / 10000 (integer, rounded)
-    (notional * fee_bps) / 10_000
+fn check_side(side: &str) -> PyResult<()> {
+    if side != "BUY" && side != "SELL" {
+        return Err(PyValueError::new_err(format!("side must be BUY or SELL, got {side}")));
+    }
+    Ok(())
 }
 
-fn apply_slippage(price: Money, slippage_bps: i64, side: &str) -> Money {
-    if slippage_bps == 0 { return price; }
+/// `price` moved `slippage_bps` plus `per_share` paise against the trade.
+fn apply_slippage(price: Money, slippage_bps: i64, per_share: Money, side: &str) -> Money {
+    if slippage_bps == 0 && per_share == 0 { return price; }
     // BUY worse (higher), SELL worse (lower)
-    let bump = (price * slippage_bps) / 10_000;
+    let bump = (price * slippage_bps) / 10_000 + per_share;
     match side {
         "BUY" => price + bump,
         "SELL" => price - bump,
@@ -91,172 +764,1938 @@ fn apply_slippage(price: Money, slippage_bps: i64, side: &str) -> Money {
     }
 }
 
+/// `price` in post-split terms after a `num`:`den` split, rounded to the nearest paisa.
+fn split_price(price: Money, num: i64, den: i64) -> Money {
+    (price.saturating_mul(den) + num / 2) / num
+}
+
+/// `price` rounded to a multiple of `tick` against the trade: up for a BUY, down for a SELL.
+fn round_to_tick(price: Money, tick: Money, side: &str) -> Money {
+    let rem = price.rem_euclid(tick);
+    if rem == 0 { return price; }
+    if side == "BUY" { price - rem + tick } else { price - rem }
+}
+
+/// Days since 1970-01-01 for a date starting with "YYYY-MM-DD", or None if it doesn't parse.
+fn day_number(date: &str) -> Option<i64> {
+    if date.get(4..5)? != "-" || date.get(7..8)? != "-" { return None; }
+    let y: i64 = date.get(0..4)?.parse().ok()?;
+    let m: i64 = date.get(5..7)?.parse().ok()?;
+    let d: i64 = date.get(8..10)?.parse().ok()?;
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) { return None; }
+    // days_from_civil over the proleptic Gregorian calendar, in 400-year eras
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146_097 + doe - 719_468)
+}
+
+/// Calendar days from `start` to `end`, 0 if either date doesn't parse.
+fn days_between(start: &str, end: &str) -> i64 {
+    match (day_number(start), day_number(end)) {
+        (Some(a), Some(b)) => b - a,
+        _ => 0,
+    }
+}
+
+/// Rate r solving sum(amount / (1 + r)^t) == 0 over (t, amount) cash flows, by bisection on
+/// (-99.99%, 1e6%). None unless the net present value changes sign on that range.
+fn irr(flows: &[(f64, f64)]) -> Option<f64> {
+    let npv = |r: f64| flows.iter().map(|(t, amount)| amount / (1.0 + r).powf(*t)).sum::<f64>();
+    let (mut lo, mut hi) = (-0.9999, 10_000.0);
+    if npv(lo).signum() == npv(hi).signum() { return None; }
+    for _ in 0..200 {
+        let mid = (lo + hi) / 2.0;
+        if npv(mid).signum() == npv(lo).signum() { lo = mid; } else { hi = mid; }
+    }
+    Some((lo + hi) / 2.0)
+}
+
+/// Quote a CSV field if it contains a delimiter, quote or newline.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Why a bar is inconsistent, or None if it's fine.
+fn bar_problem(bar: &Bar) -> Option<&'static str> {
+    if bar.low > bar.high {
+        Some("low > high")
+    } else if bar.open < bar.low || bar.open > bar.high {
+        Some("open outside [low, high]")
+    } else if bar.close < bar.low || bar.close > bar.high {
+        Some("close outside [low, high]")
+    } else if bar.volume < 0 {
+        Some("negative volume")
+    } else {
+        None
+    }
+}
+
+/// Execution price for a pending order on this bar, or None if it can't fill yet.
+/// A gap through a limit fills at the (better) open; a gap through a stop fills at the (worse) open.
+fn fill_price(bar: &Bar, order: &PendingOrder, slippage_bps: i64, per_share: Money) -> Option<Money> {
+    let side = order.side.as_str();
+    match order.kind {
+        OrderType::Market => Some(apply_slippage(bar.open, slippage_bps, per_share, side)),
+        // never trade through the limit, so no slippage
+        OrderType::Limit(limit) => match side {
+            "BUY" if bar.low <= limit => Some(bar.open.min(limit)),
+            "SELL" if bar.high >= limit => Some(bar.open.max(limit)),
+            _ => None,
+        },
+        OrderType::Stop(stop) => stop_fill_price(bar, side, stop, slippage_bps, per_share),
+        OrderType::TrailingStop { .. } => stop_fill_price(bar, side, order.kind.level(side)?, slippage_bps, per_share),
+    }
+}
+
+fn stop_fill_price(bar: &Bar, side: &str, stop: Money, slippage_bps: i64, per_share: Money) -> Option<Money> {
+    match side {
+        "SELL" if bar.low <= stop => Some(apply_slippage(bar.open.min(stop), slippage_bps, per_share, side)),
+        "BUY" if bar.high >= stop => Some(apply_slippage(bar.open.max(stop), slippage_bps, per_share, side)),
+        _ => None,
+    }
+}
+
 #[pymethods]
 impl Engine {
+    /// `reject_on_insufficient_cash`: drop BUYs whose notional + fee exceeds current settled
+    /// cash instead of letting cash go negative; see `rejected_orders`. With `leverage_limit`
+    /// set, the leverage check applies instead.
+    /// `allow_short`: let a SELL beyond the current long open a short. Off by default: the fill
+    /// is clamped to the long, closing it, the excess is dropped and logged in
+    /// `truncated_orders`, and a SELL with no long to close is rejected as "SHORT_NOT_ALLOWED".
+    /// Positions seeded short by `set_initial_position` are kept either way.
+    /// `settlement_days`: T+N settlement of sale proceeds (including short sales). Proceeds
+    /// count in cash and equity at once but stay in `unsettled_cash` until N `end_of_period`
+    /// marks have passed, the sale date's own included, so T+2 proceeds from Monday's fill can
+    /// pay for Wednesday's. Only the cash rejection check respects it. Raises ValueError if
+    /// negative.
+    /// `max_drawdown_stop_pct`: kill switch. The first `end_of_period` mark more than this many
+    /// percent below peak equity (see `current_drawdown_pct`) cancels every pending order and
+    /// queues market orders closing all positions, as `liquidate_all`, to fill on the next
+    /// pass; from then on every new order is rejected as "DRAWDOWN_STOP". It fires at most
+    /// once; see `drawdown_stop_date`. None disables it.
+    /// `default_risk_pct`: in `open_risk_paise`, the percent of its avg-cost value that position
+    /// qty without a protective stop is taken to risk. None counts it as no risk.
+    /// `max_participation_pct`: cap each order's fill on a bar at `volume * pct / 100` shares;
+    /// the remainder stays pending for the next bar. None means unlimited liquidity.
+    /// `strict`: raise ValueError on data problems (bad bars, orders or positions without a bar
+    /// for the date) instead of silently skipping them.
+    /// `record_calls`: log every state-changing call with its arguments, so the run can be sent
+    /// as `call_log()` and rebuilt with `Engine.replay`.
+    /// `annual_cash_rate_bps` / `annual_borrow_rate_bps`: interest credited on positive cash or
+    /// charged on negative cash, accrued at rate / periods_per_year in each `end_of_period`.
+    /// `periods_per_year`: how many `end_of_period` marks make a year (252 for daily bars,
+    /// e.g. 52 for weekly, 12 for monthly); annualizes sharpe, sortino, annual_return_pct, cagr_pct,
+    /// volatility, alpha and interest. Raises ValueError unless positive.
+    /// `calendar_aware`: annualize the metrics by the calendar time the equity curve covers
+    /// rather than by `periods_per_year`: with years = days from the first to the last mark /
+    /// 365.25, a year is (marks - 1) / years marks (see `metric_periods_per_year`), so curves
+    /// with weekend marks or holiday gaps annualize correctly. Falls back to `periods_per_year`
+    /// when there are fewer than two marks or their dates don't parse as YYYY-MM-DD. Interest
+    /// still accrues per mark at 1 / `periods_per_year`.
+    /// `trading_calendar`: the dates `end_of_period` may be called with, compared as strings;
+    /// any other date raises ValueError. Calendar dates passed over between two marks are
+    /// listed in `missing_days`. With `fill_missing_days` each of them also gets a mark
+    /// repeating the previous one (no interest, fills or callbacks), so the curve has no gaps.
+    /// `risk_free_annual_pct`: Rf for the metrics, as a simple annual percentage. Each per-period
+    /// log return has `risk_free_annual_pct / 100 / periods_per_year` subtracted before sharpe,
+    /// sortino (where it is also the MAR) and alpha are computed.
+    /// `omega_threshold_pct`: the per-period return, as a simple percentage, that the omega
+    /// ratio counts gains above and losses below, e.g. 0.02 for 2 bps a mark. It is compared as
+    /// the log return ln(1 + threshold / 100), in the units of `log_returns`.
+    /// `fill_timing`: reference price for fills, see `FillTiming`.
+    /// `fill_priority`: order in which pending orders fill, see `FillPriority`. Execution has
+    /// no random component: ties are broken by `fill_priority` then order_id, so two engines
+    /// fed the same calls produce identical fills.
+    /// `rounding`: how fees and the avg cost of adds are rounded to whole paise, see
+    /// `RoundingMode`.
+    /// `reject_over_position_limit`: reject fills that would breach a position limit (see
+    /// `set_max_position_qty` / `set_max_notional_pct`) instead of truncating them to it.
+    /// `duplicate_bars`: handling of a repeated (symbol, date) bar, see `DuplicateBars`.
+    /// `cost_basis`: which lots a reducing fill realizes PnL against, see `CostBasis`.
+    /// `leverage_limit`: margin account limit on gross exposure (sum of |position value|) as a
+    /// multiple of equity, e.g. 2.0. Buying beyond cash borrows it, so the borrowed portion is
+    /// negative cash charged at `annual_borrow_rate_bps`. A fill that would raise gross exposure
+    /// above the limit, marked at the fill price and last closes and net of its fee, is rejected
+    /// as "LEVERAGE_LIMIT"; this replaces the `reject_on_insufficient_cash` check, so buys may
+    /// use the borrowing power. An `end_of_period` mark over the limit (or with gross exposure
+    /// and no positive equity) is logged in `margin_calls`; positions are not liquidated. None
+    /// disables both checks.
+    /// `halt_on_blowup`: once an `end_of_period` mark has equity <= 0 (see `blown_up_date`),
+    /// stop filling orders; they stay pending. Metrics flag a blown-up run either way.
+    /// `daily_loss_limit_paise`: circuit breaker on new orders. The day's loss is equity when
+    /// the day began less current equity at the latest closes, so it covers realized and
+    /// unrealized PnL, fees and dividends but not deposits or withdrawals. While it exceeds
+    /// the limit, every place_* and order_target_* call records its order in `rejected_orders`
+    /// as "DAILY_LOSS_LIMIT" instead of queueing it; `liquidate_all` and orders already pending
+    /// are unaffected. A day begins with the first bar or `process_fills_for_date` call dated
+    /// on a later YYYY-MM-DD than any before it, whether or not `end_of_period` was called in
+    /// between; the baseline is equity at the closes just before it (before the first day,
+    /// the last mark or starting equity). None disables it.
+    /// `impact_bps`: volume-aware slippage; a fill of `qty` on a bar of `volume` slips
+    /// `slippage_bps + impact_bps * qty / volume` (rounded to whole bps). When set, orders that
+    /// would fill on a zero-volume bar are rejected as "ZERO_VOLUME".
+    /// `gap_slippage_coeff` / `gap_threshold_bps`: with NextOpen timing, a market order filling
+    /// at an open that gapped more than `gap_threshold_bps` from the prior close slips an extra
+    /// `gap_slippage_coeff * (gap_bps - gap_threshold_bps)` (rounded to whole bps).
+    /// `fee_model`: commission as a dict, e.g. `{"type": "MAX", "bps": 3, "amount": 2000}`; see
+    /// `FeeModel` for the types. Defaults to `fee_bps` of notional.
+    /// `slippage_model`: base slippage of market and stop fills as a dict, in the symbol's own
+    /// currency: `{"type": "FIXED", "amount": 5}` moves each fill 5 paise a share against the
+    /// trade and `{"type": "TICKS", "ticks": 2, "amount": 5}` two 5-paise ticks, where bps would
+    /// round to nothing on low-priced instruments; `{"type": "BPS", "bps": 3}` is the default
+    /// of `slippage_bps`. Impact and gap slippage add their bps on top of either.
+    /// `min_fee_paise`: floor on the fee of each order. A fill is charged at least what brings
+    /// the order's fees so far up to it, so an order filled in parts pays it once.
+    /// `maker_fee_bps` / `taker_fee_bps`: bps of notional charged on limit fills (maker) and on
+    /// market and stop fills (taker) instead of the fee model. A negative rate is a rebate that
+    /// credits cash and counts in `rebates_paise` rather than `fees_paise`; `min_fee_paise` is
+    /// not applied to rebates. A negative `fee_bps` or fee model rate works the same way.
     #[new]
-    fn new(starting_cash_paise: i64, fee_bps: i64, slippage_bps: i64) -> Self {
-        Engine {
+    #[pyo3(signature = (
+        starting_cash_paise,
+        fee_bps,
+        slippage_bps,
+        reject_on_insufficient_cash=false,
+        max_participation_pct=None,
+        strict=false,
+        annual_cash_rate_bps=0,
+        annual_borrow_rate_bps=0,
+        fill_timing=FillTiming::NextOpen,
+        fee_model=None,
+        slippage_model=None,
+        impact_bps=0.0,
+        min_fee_paise=0,
+        periods_per_year=252.0,
+        risk_free_annual_pct=0.0,
+        omega_threshold_pct=0.0,
+        fill_priority=FillPriority::FifoByOrderId,
+        rounding=RoundingMode::Truncate,
+        duplicate_bars=DuplicateBars::Overwrite,
+        reject_over_position_limit=false,
+        cost_basis=CostBasis::Average,
+        leverage_limit=None,
+        gap_slippage_coeff=0.0,
+        gap_threshold_bps=0,
+        halt_on_blowup=false,
+        maker_fee_bps=None,
+        taker_fee_bps=None,
+        daily_loss_limit_paise=None,
+        settlement_days=0,
+        max_drawdown_stop_pct=None,
+        default_risk_pct=None,
+        calendar_aware=false,
+        trading_calendar=None,
+        fill_missing_days=false,
+        allow_short=false,
+        record_calls=false,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        starting_cash_paise: i64,
+        fee_bps: i64,
+        slippage_bps: i64,
+        reject_on_insufficient_cash: bool,
+        max_participation_pct: Option<f64>,
+        strict: bool,
+        annual_cash_rate_bps: i64,
+        annual_borrow_rate_bps: i64,
+        fill_timing: FillTiming,
+        fee_model: Option<FeeModel>,
+        slippage_model: Option<SlippageModel>,
+        impact_bps: f64,
+        min_fee_paise: i64,
+        periods_per_year: f64,
+        risk_free_annual_pct: f64,
+        omega_threshold_pct: f64,
+        fill_priority: FillPriority,
+        rounding: RoundingMode,
+        duplicate_bars: DuplicateBars,
+        reject_over_position_limit: bool,
+        cost_basis: CostBasis,
+        leverage_limit: Option<f64>,
+        gap_slippage_coeff: f64,
+        gap_threshold_bps: i64,
+        halt_on_blowup: bool,
+        maker_fee_bps: Option<i64>,
+        taker_fee_bps: Option<i64>,
+        daily_loss_limit_paise: Option<i64>,
+        settlement_days: i64,
+        max_drawdown_stop_pct: Option<f64>,
+        default_risk_pct: Option<f64>,
+        calendar_aware: bool,
+        trading_calendar: Option<Vec<String>>,
+        fill_missing_days: bool,
+        allow_short: bool,
+        record_calls: bool,
+    ) -> PyResult<Self> {
+        if !(periods_per_year.is_finite() && periods_per_year > 0.0) {
+            return Err(PyValueError::new_err(format!("periods_per_year must be positive, got {periods_per_year}")));
+        }
+        if settlement_days < 0 {
+            return Err(PyValueError::new_err(format!("settlement_days must be >= 0, got {settlement_days}")));
+        }
+        Ok(Engine {
             starting_cash: starting_cash_paise,
+            starting_equity: starting_cash_paise,
             cash: starting_cash_paise,
-            fee_bps,
-            slippage_bps,
+            fee_model: fee_model.unwrap_or(FeeModel::Bps(fee_bps)),
+            fee_fn: None,
+            bar_fn: None,
+            fee_bps_by_symbol: HashMap::new(),
+            min_fee: min_fee_paise,
+            maker_fee_bps,
+            taker_fee_bps,
+            slippage_model: slippage_model.unwrap_or(SlippageModel::Bps(slippage_bps)),
+            impact_bps,
+            gap_slippage_coeff,
+            gap_threshold_bps,
+            reject_on_insufficient_cash,
+            reject_over_position_limit,
+            allow_short,
+            max_position_qty: HashMap::new(),
+            lot_sizes: HashMap::new(),
+            tick_sizes: HashMap::new(),
+            max_notional_pct: None,
+            warmup_until: None,
+            max_participation_pct,
+            strict,
+            record_calls,
+            fill_timing,
+            fill_priority,
+            annual_cash_rate_bps,
+            annual_borrow_rate_bps,
+            periods_per_year,
+            calendar_aware,
+            trading_calendar: trading_calendar.map(|mut dates| {
+                dates.sort();
+                dates.dedup();
+                dates
+            }),
+            fill_missing_days,
+            risk_free_annual_pct,
+            omega_threshold_pct,
+            rounding,
+            duplicate_bars,
+            cost_basis,
+            leverage_limit,
+            halt_on_blowup,
+            daily_loss_limit: daily_loss_limit_paise,
+            settlement_days,
+            max_drawdown_stop_pct,
+            default_risk_pct,
             last_bar_by_symbol: HashMap::new(),
+            prev_close: HashMap::new(),
+            bad_bars: 0,
+            duplicate_bars_seen: 0,
+            bars_seen: 0,
+            first_bar_date: String::new(),
+            last_bar_date: String::new(),
             pending_orders: Vec::new(),
             next_order_id: 1,
+            rejected_orders: Vec::new(),
+            expired_orders: Vec::new(),
+            truncated_orders: Vec::new(),
+            lot_adjustments: Vec::new(),
+            margin_calls: Vec::new(),
+            unsettled: Vec::new(),
             positions: HashMap::new(),
+            entry_dates: HashMap::new(),
             avg_cost: HashMap::new(),
+            basis: HashMap::new(),
+            open_slippage: HashMap::new(),
+            open_fees: HashMap::new(),
+            lots: HashMap::new(),
+            excursions: HashMap::new(),
             fills: Vec::new(),
+            closed_trades: Vec::new(),
+            dividends: Vec::new(),
+            cash_flows: Vec::new(),
+            call_log: Vec::new(),
+            call_log_start: None,
             equity_curve: Vec::new(),
+            exposure_curve: Vec::new(),
+            missing_days: Vec::new(),
+            mark_flows: Vec::new(),
+            pending_flow: 0,
+            loss_day: String::new(),
+            loss_day_equity: 0,
+            benchmark: HashMap::new(),
+            symbol_currency: HashMap::new(),
+            fx_rates: HashMap::new(),
             realized_pnl: 0,
             fees_paid: 0,
+            rebates: 0,
+            slippage_paid: 0,
+            interest: 0,
             trades_closed: 0,
             wins: 0,
+            gross_profit: 0,
+            gross_loss: 0,
             peak_equity: starting_cash_paise,
+            peak_date: String::new(),
             max_dd: 0,
-        }
+            max_dd_days: 0,
+            blown_up_date: None,
+            drawdown_stop_date: None,
+            exposure_sum: 0.0,
+        })
     }
 
-    /// Called once per symbol per day (worker will call in a loop).
-    fn on_bar(&mut self, date: String, symbol: String, open_paise: i64, high_paise: i64, low_paise: i64, close_paise: i64, volume: i64) {
-        let bar = Bar { date: date.clone(), symbol: symbol.clone(), open: open_paise, high: high_paise, low: low_paise, close: close_paise, volume };
-        self.last_bar_by_symbol.insert(symbol, bar);
+    /// Checkpoint the complete engine (config and state) as JSON with sorted keys, so two
+    /// runs can be diffed. Restore with `Engine.from_json`.
+    fn to_json(&self, py: Python<'_>) -> PyResult<String> {
+        let d = PyDict::new_bound(py);
+        d.set_item("starting_cash", self.starting_cash)?;
+        d.set_item("starting_equity", self.starting_equity)?;
+        d.set_item("cash", self.cash)?;
+        d.set_item("fee_model", self.fee_model)?;
+        d.set_item("fee_bps_by_symbol", &self.fee_bps_by_symbol)?;
+        d.set_item("min_fee", self.min_fee)?;
+        d.set_item("maker_fee_bps", self.maker_fee_bps)?;
+        d.set_item("taker_fee_bps", self.taker_fee_bps)?;
+        d.set_item("slippage_model", self.slippage_model)?;
+        d.set_item("impact_bps", self.impact_bps)?;
+        d.set_item("gap_slippage_coeff", self.gap_slippage_coeff)?;
+        d.set_item("gap_threshold_bps", self.gap_threshold_bps)?;
+        d.set_item("reject_on_insufficient_cash", self.reject_on_insufficient_cash)?;
+        d.set_item("reject_over_position_limit", self.reject_over_position_limit)?;
+        d.set_item("allow_short", self.allow_short)?;
+        d.set_item("max_position_qty", &self.max_position_qty)?;
+        d.set_item("lot_sizes", &self.lot_sizes)?;
+        d.set_item("tick_sizes", &self.tick_sizes)?;
+        d.set_item("max_notional_pct", self.max_notional_pct)?;
+        d.set_item("warmup_until", &self.warmup_until)?;
+        d.set_item("max_participation_pct", self.max_participation_pct)?;
+        d.set_item("strict", self.strict)?;
+        d.set_item("record_calls", self.record_calls)?;
+        d.set_item("fill_timing", self.fill_timing.name())?;
+        d.set_item("fill_priority", self.fill_priority.name())?;
+        d.set_item("annual_cash_rate_bps", self.annual_cash_rate_bps)?;
+        d.set_item("annual_borrow_rate_bps", self.annual_borrow_rate_bps)?;
+        d.set_item("periods_per_year", self.periods_per_year)?;
+        d.set_item("calendar_aware", self.calendar_aware)?;
+        d.set_item("trading_calendar", &self.trading_calendar)?;
+        d.set_item("fill_missing_days", self.fill_missing_days)?;
+        d.set_item("risk_free_annual_pct", self.risk_free_annual_pct)?;
+        d.set_item("omega_threshold_pct", self.omega_threshold_pct)?;
+        d.set_item("rounding", self.rounding.name())?;
+        d.set_item("duplicate_bars", self.duplicate_bars.name())?;
+        d.set_item("cost_basis", self.cost_basis.name())?;
+        d.set_item("leverage_limit", self.leverage_limit)?;
+        d.set_item("halt_on_blowup", self.halt_on_blowup)?;
+        d.set_item("daily_loss_limit", self.daily_loss_limit)?;
+        d.set_item("settlement_days", self.settlement_days)?;
+        d.set_item("max_drawdown_stop_pct", self.max_drawdown_stop_pct)?;
+        d.set_item("default_risk_pct", self.default_risk_pct)?;
+        d.set_item("last_bar_by_symbol", &self.last_bar_by_symbol)?;
+        d.set_item("prev_close", &self.prev_close)?;
+        d.set_item("bad_bars", self.bad_bars)?;
+        d.set_item("duplicate_bars_seen", self.duplicate_bars_seen)?;
+        d.set_item("bars_seen", self.bars_seen)?;
+        d.set_item("first_bar_date", &self.first_bar_date)?;
+        d.set_item("last_bar_date", &self.last_bar_date)?;
+        d.set_item("pending_orders", &self.pending_orders)?;
+        d.set_item("next_order_id", self.next_order_id)?;
+        d.set_item("rejected_orders", &self.rejected_orders)?;
+        d.set_item("expired_orders", &self.expired_orders)?;
+        d.set_item("truncated_orders", &self.truncated_orders)?;
+        d.set_item("lot_adjustments", &self.lot_adjustments)?;
+        d.set_item("margin_calls", &self.margin_calls)?;
+        d.set_item("unsettled", &self.unsettled)?;
+        d.set_item("positions", &self.positions)?;
+        d.set_item("entry_dates", &self.entry_dates)?;
+        d.set_item("avg_cost", &self.avg_cost)?;
+        d.set_item("basis", &self.basis)?;
+        d.set_item("open_slippage", &self.open_slippage)?;
+        d.set_item("open_fees", &self.open_fees)?;
+        d.set_item("lots", &self.lots)?;
+        d.set_item("excursions", &self.excursions)?;
+        d.set_item("fills", &self.fills)?;
+        d.set_item("closed_trades", &self.closed_trades)?;
+        d.set_item("dividends", &self.dividends)?;
+        d.set_item("cash_flows", &self.cash_flows)?;
+        d.set_item("call_log", &self.call_log)?;
+        d.set_item("call_log_start", &self.call_log_start)?;
+        d.set_item("benchmark", &self.benchmark)?;
+        d.set_item("symbol_currency", &self.symbol_currency)?;
+        d.set_item("fx_rates", &self.fx_rates)?;
+        d.set_item("equity_curve", &self.equity_curve)?;
+        d.set_item("exposure_curve", &self.exposure_curve)?;
+        d.set_item("missing_days", &self.missing_days)?;
+        d.set_item("mark_flows", &self.mark_flows)?;
+        d.set_item("pending_flow", self.pending_flow)?;
+        d.set_item("loss_day", &self.loss_day)?;
+        d.set_item("loss_day_equity", self.loss_day_equity)?;
+        d.set_item("realized_pnl", self.realized_pnl)?;
+        d.set_item("fees_paid", self.fees_paid)?;
+        d.set_item("rebates", self.rebates)?;
+        d.set_item("slippage_paid", self.slippage_paid)?;
+        d.set_item("interest", self.interest)?;
+        d.set_item("trades_closed", self.trades_closed)?;
+        d.set_item("wins", self.wins)?;
+        d.set_item("gross_profit", self.gross_profit)?;
+        d.set_item("gross_loss", self.gross_loss)?;
+        d.set_item("peak_equity", self.peak_equity)?;
+        d.set_item("peak_date", &self.peak_date)?;
+        d.set_item("max_dd", self.max_dd)?;
+        d.set_item("max_dd_days", self.max_dd_days)?;
+        d.set_item("blown_up_date", &self.blown_up_date)?;
+        d.set_item("drawdown_stop_date", &self.drawdown_stop_date)?;
+        d.set_item("exposure_sum", self.exposure_sum)?;
 
-        // We only append equity point once per date; simplest approach:
-        // worker can call engine.end_of_day(date) once per date after all symbols processed.
-        // So do nothing here.
+        let kwargs = [("sort_keys", true)].into_py_dict_bound(py);
+        py.import_bound("json")?.call_method("dumps", (d,), Some(&kwargs))?.extract()
     }
 
-    /// Strategy calls this through ctx.buy/sell. Market order only for MVP.
-    fn place_market_order(&mut self, symbol: String, side: String, qty: i64) -> i64 {
-        let oid = self.next_order_id;
-        self.next_order_id += 1;
-        self.pending_orders.push((oid, symbol, side, qty));
-        oid
+    /// With `record_calls`, the run so far as JSON: {"engine": the engine before its first
+    /// state-changing call, as `to_json`, "calls": [[method, [args...], error], ...]}, error
+    /// being the message the call raised, or None. Wrappers are logged as the calls they make
+    /// (`end_of_day` as `end_of_period`, `on_bars_batch` as each `on_bar`,
+    /// `order_target_percent` as `order_target_notional`), and `reset` starts a new log. `set_fee_fn` and `set_bar_callback` can't be logged, so a run priced by a fee
+    /// callback won't replay identically. Raises ValueError unless `record_calls` is on.
+    fn call_log(&self, py: Python<'_>) -> PyResult<String> {
+        if !self.record_calls {
+            return Err(PyValueError::new_err("call_log needs an engine created with record_calls=True"));
+        }
+        let json = py.import_bound("json")?;
+        let start = match &self.call_log_start {
+            Some(start) => start.clone(),
+            None => self.to_json(py)?,
+        };
+        let d = PyDict::new_bound(py);
+        d.set_item("engine", json.call_method1("loads", (start,))?)?;
+        d.set_item("calls", &self.call_log)?;
+        json.call_method1("dumps", (d,))?.extract()
     }
 
-    /// Execute fills on NEXT_OPEN using next day's open, so worker should call this at the *start* of day
-    /// after loading bars for that date (bars already set via on_bar).
-    fn process_fills_for_date(&mut self, date: String) {
-        // Fill any orders using today's open for that symbol
-        let mut still_pending = Vec::new();
-
-        for (oid, sym, side, qty) in self.pending_orders.drain(..) {
-            let bar = match self.last_bar_by_symbol.get(&sym) {
-                Some(b) if b.date == date => b.clone(),
-                _ => { still_pending.push((oid, sym, side, qty)); continue; }
-            };
-
-            let mut px = bar.open;
-            px = apply_slippage(px, self.slippage_bps, &side);
-            let notional = px.saturating_mul(qty.abs());
-            let fee = fee_for(notional, self.fee_bps);
-
-            // Update cash & position (long-only MVP but allow sell to reduce)
-            if side == "BUY" {
-                let cost = notional + fee;
-                self.cash -= cost;
-                let old_q = *self.positions.get(&sym).unwrap_or(&0);
-                let new_q = old_q + qty;
-
-                // avg cost update (only for long)
-                let old_avg = *self.avg_cost.get(&sym).unwrap_or(&0);
-                let new_avg = if new_q > 0 {
-                    // weighted avg
-                    let old_notional = old_avg.saturating_mul(old_q.max(0));
-                    let add_notional = px.saturating_mul(qty);
-                    (old_notional + add_notional) / new_q
-                } else { 0 };
-                self.positions.insert(sym.clone(), new_q);
-                self.avg_cost.insert(sym.clone(), new_avg);
-            } else if side == "SELL" {
-                let proceeds = notional - fee;
-                self.cash += proceeds;
-
-                let old_q = *self.positions.get(&sym).unwrap_or(&0);
-                let sell_qty = qty; // expect positive qty passed for sell
-                let new_q = old_q - sell_qty;
-                self.positions.insert(sym.clone(), new_q);
-
-                // Realized PnL for long reductions only
-                let avg = *self.avg_cost.get(&sym).unwrap_or(&0);
-                let pnl = (px - avg).saturating_mul(sell_qty);
-                self.realized_pnl += pnl;
-                self.trades_closed += 1;
-                if pnl > 0 { self.wins += 1; }
-
-                if new_q <= 0 {
-                    self.avg_cost.insert(sym.clone(), 0);
-                }
+    /// Rebuild the run recorded in a `call_log()` by making the same calls, in order, on the
+    /// engine it started from. Execution is deterministic, so fills, equity and metrics come out
+    /// identical, and so does the new engine's own call log. A call that raised when it was
+    /// recorded raises again here and is skipped, as it was then; one that raises only here
+    /// (a fee callback the log can't carry, or a hand-edited log) raises ValueError naming it.
+    #[staticmethod]
+    fn replay(py: Python<'_>, log: &str) -> PyResult<Py<Engine>> {
+        let json = py.import_bound("json")?;
+        let d = json.call_method1("loads", (log,))?;
+        let start: String = json.call_method1("dumps", (d.get_item("engine")?,))?.extract()?;
+        let engine = Bound::new(py, Engine::from_json(py, &start)?)?;
+        for call in d.get_item("calls")?.iter()? {
+            let call = call?;
+            let method: String = call.get_item(0)?.extract()?;
+            let args = call.get_item(1)?.downcast::<PyList>()?.to_tuple();
+            let raised: Option<String> = call.get_item(2)?.extract()?;
+            if let Err(e) = engine.call_method1(method.as_str(), args) && raised.is_none() {
+                return Err(PyValueError::new_err(format!("replay: {method} raised {e}, but not when it was recorded")));
             }
-
-            self.fees_paid += fee;
-
-            self.fills.push(Fill {
-                date: date.clone(),
-                symbol: sym.clone(),
-                side: side.clone(),
-                qty,
-                price: px,
-                fee,
-                order_id: oid,
-            });
         }
-
-        self.pending_orders = still_pending;
+        Ok(engine.unbind())
     }
 
-    /// Mark end-of-day equity point (cash + sum(pos * close)).
-    fn end_of_day(&mut self, date: String) {
-        let mut equity = self.cash;
-        for (sym, q) in self.positions.iter() {
-            if *q == 0 { continue; }
-            if let Some(bar) = self.last_bar_by_symbol.get(sym) {
-                if bar.date == date {
-                    equity += bar.close.saturating_mul(*q);
-                }
-            }
+    /// Rebuild an engine from `to_json` output. Pending orders are checked like new ones, so an
+    /// edited checkpoint can't queue a zero or negative qty that would fill as nothing.
+    #[staticmethod]
+    fn from_json(py: Python<'_>, s: &str) -> PyResult<Engine> {
+        let d = py.import_bound("json")?.call_method1("loads", (s,))?;
+        let pending_orders: Vec<PendingOrder> = d.get_item("pending_orders")?.extract()?;
+        for o in &pending_orders {
+            check_order(&o.side, o.qty, &o.tif)?;
         }
-        self.equity_curve.push((date.clone(), equity));
+        let fill_timing: String = d.get_item("fill_timing")?.extract()?;
+        let fill_priority: String = d.get_item("fill_priority")?.extract()?;
+        let rounding: String = d.get_item("rounding")?.extract()?;
+        let duplicate_bars: String = d.get_item("duplicate_bars")?.extract()?;
+        let cost_basis: String = d.get_item("cost_basis")?.extract()?;
+        Ok(Engine {
+            starting_cash: d.get_item("starting_cash")?.extract()?,
+            starting_equity: d.get_item("starting_equity")?.extract()?,
+            cash: d.get_item("cash")?.extract()?,
+            fee_model: d.get_item("fee_model")?.extract()?,
+            fee_fn: None,
+            bar_fn: None,
+            fee_bps_by_symbol: d.get_item("fee_bps_by_symbol")?.extract()?,
+            min_fee: d.get_item("min_fee")?.extract()?,
+            maker_fee_bps: d.get_item("maker_fee_bps")?.extract()?,
+            taker_fee_bps: d.get_item("taker_fee_bps")?.extract()?,
+            slippage_model: d.get_item("slippage_model")?.extract()?,
+            impact_bps: d.get_item("impact_bps")?.extract()?,
+            gap_slippage_coeff: d.get_item("gap_slippage_coeff")?.extract()?,
+            gap_threshold_bps: d.get_item("gap_threshold_bps")?.extract()?,
+            reject_on_insufficient_cash: d.get_item("reject_on_insufficient_cash")?.extract()?,
+            reject_over_position_limit: d.get_item("reject_over_position_limit")?.extract()?,
+            allow_short: d.get_item("allow_short")?.extract()?,
+            max_position_qty: d.get_item("max_position_qty")?.extract()?,
+            lot_sizes: d.get_item("lot_sizes")?.extract()?,
+            tick_sizes: d.get_item("tick_sizes")?.extract()?,
+            max_notional_pct: d.get_item("max_notional_pct")?.extract()?,
+            warmup_until: d.get_item("warmup_until")?.extract()?,
+            max_participation_pct: d.get_item("max_participation_pct")?.extract()?,
+            strict: d.get_item("strict")?.extract()?,
+            record_calls: d.get_item("record_calls")?.extract()?,
+            fill_timing: FillTiming::from_name(&fill_timing)?,
+            fill_priority: FillPriority::from_name(&fill_priority)?,
+            annual_cash_rate_bps: d.get_item("annual_cash_rate_bps")?.extract()?,
+            annual_borrow_rate_bps: d.get_item("annual_borrow_rate_bps")?.extract()?,
+            periods_per_year: d.get_item("periods_per_year")?.extract()?,
+            calendar_aware: d.get_item("calendar_aware")?.extract()?,
+            trading_calendar: d.get_item("trading_calendar")?.extract()?,
+            fill_missing_days: d.get_item("fill_missing_days")?.extract()?,
+            risk_free_annual_pct: d.get_item("risk_free_annual_pct")?.extract()?,
+            omega_threshold_pct: d.get_item("omega_threshold_pct")?.extract()?,
+            rounding: RoundingMode::from_name(&rounding)?,
+            duplicate_bars: DuplicateBars::from_name(&duplicate_bars)?,
+            cost_basis: CostBasis::from_name(&cost_basis)?,
+            leverage_limit: d.get_item("leverage_limit")?.extract()?,
+            halt_on_blowup: d.get_item("halt_on_blowup")?.extract()?,
+            daily_loss_limit: d.get_item("daily_loss_limit")?.extract()?,
+            settlement_days: d.get_item("settlement_days")?.extract()?,
+            max_drawdown_stop_pct: d.get_item("max_drawdown_stop_pct")?.extract()?,
+            default_risk_pct: d.get_item("default_risk_pct")?.extract()?,
+            last_bar_by_symbol: d.get_item("last_bar_by_symbol")?.extract()?,
+            prev_close: d.get_item("prev_close")?.extract()?,
+            bad_bars: d.get_item("bad_bars")?.extract()?,
+            duplicate_bars_seen: d.get_item("duplicate_bars_seen")?.extract()?,
+            bars_seen: d.get_item("bars_seen")?.extract()?,
+            first_bar_date: d.get_item("first_bar_date")?.extract()?,
+            last_bar_date: d.get_item("last_bar_date")?.extract()?,
+            pending_orders,
+            next_order_id: d.get_item("next_order_id")?.extract()?,
+            rejected_orders: extract_rows(&d.get_item("rejected_orders")?)?,
+            expired_orders: extract_rows(&d.get_item("expired_orders")?)?,
+            truncated_orders: extract_rows(&d.get_item("truncated_orders")?)?,
+            lot_adjustments: extract_rows(&d.get_item("lot_adjustments")?)?,
+            margin_calls: extract_rows(&d.get_item("margin_calls")?)?,
+            unsettled: extract_rows(&d.get_item("unsettled")?)?,
+            positions: d.get_item("positions")?.extract()?,
+            entry_dates: d.get_item("entry_dates")?.extract()?,
+            avg_cost: d.get_item("avg_cost")?.extract()?,
+            basis: d.get_item("basis")?.extract()?,
+            open_slippage: d.get_item("open_slippage")?.extract()?,
+            open_fees: d.get_item("open_fees")?.extract()?,
+            lots: d.get_item("lots")?.extract()?,
+            excursions: d.get_item("excursions")?.extract::<HashMap<String, [Money; 2]>>()?
+                .into_iter().map(|(sym, [lo, hi])| (sym, (lo, hi))).collect(),
+            fills: d.get_item("fills")?.extract()?,
+            closed_trades: d.get_item("closed_trades")?.extract()?,
+            dividends: extract_rows(&d.get_item("dividends")?)?,
+            cash_flows: extract_rows(&d.get_item("cash_flows")?)?,
+            call_log: extract_rows(&d.get_item("call_log")?)?,
+            call_log_start: d.get_item("call_log_start")?.extract()?,
+            benchmark: d.get_item("benchmark")?.extract()?,
+            symbol_currency: d.get_item("symbol_currency")?.extract()?,
+            fx_rates: d.get_item("fx_rates")?.extract()?,
+            equity_curve: extract_rows(&d.get_item("equity_curve")?)?,
+            exposure_curve: extract_rows(&d.get_item("exposure_curve")?)?,
+            missing_days: d.get_item("missing_days")?.extract()?,
+            mark_flows: d.get_item("mark_flows")?.extract()?,
+            pending_flow: d.get_item("pending_flow")?.extract()?,
+            loss_day: d.get_item("loss_day")?.extract()?,
+            loss_day_equity: d.get_item("loss_day_equity")?.extract()?,
+            realized_pnl: d.get_item("realized_pnl")?.extract()?,
+            fees_paid: d.get_item("fees_paid")?.extract()?,
+            rebates: d.get_item("rebates")?.extract()?,
+            slippage_paid: d.get_item("slippage_paid")?.extract()?,
+            interest: d.get_item("interest")?.extract()?,
+            trades_closed: d.get_item("trades_closed")?.extract()?,
+            wins: d.get_item("wins")?.extract()?,
+            gross_profit: d.get_item("gross_profit")?.extract()?,
+            gross_loss: d.get_item("gross_loss")?.extract()?,
+            peak_equity: d.get_item("peak_equity")?.extract()?,
+            peak_date: d.get_item("peak_date")?.extract()?,
+            max_dd: d.get_item("max_dd")?.extract()?,
+            max_dd_days: d.get_item("max_dd_days")?.extract()?,
+            blown_up_date: d.get_item("blown_up_date")?.extract()?,
+            drawdown_stop_date: d.get_item("drawdown_stop_date")?.extract()?,
+            exposure_sum: d.get_item("exposure_sum")?.extract()?,
+        })
+    }
 
-        // Drawdown tracking
-        if equity > self.peak_equity { self.peak_equity = equity; }
-        let dd = self.peak_equity - equity;
-        if dd > self.max_dd { self.max_dd = dd; }
+    /// New engine with this one's config (constructor arguments, fee callback, per-symbol
+    /// fee rates and position limits, benchmark, currencies and FX rates) and no state: cash
+    /// back at starting cash, no bars, orders, positions or history. Initial positions are
+    /// state and are not copied.
+    fn clone_config(&self, py: Python<'_>) -> Engine {
+        Engine {
+            starting_cash: self.starting_cash,
+            starting_equity: self.starting_cash,
+            cash: self.starting_cash,
+            fee_model: self.fee_model,
+            fee_fn: self.fee_fn.as_ref().map(|f| f.clone_ref(py)),
+            bar_fn: self.bar_fn.as_ref().map(|f| f.clone_ref(py)),
+            fee_bps_by_symbol: self.fee_bps_by_symbol.clone(),
+            min_fee: self.min_fee,
+            maker_fee_bps: self.maker_fee_bps,
+            taker_fee_bps: self.taker_fee_bps,
+            slippage_model: self.slippage_model,
+            impact_bps: self.impact_bps,
+            gap_slippage_coeff: self.gap_slippage_coeff,
+            gap_threshold_bps: self.gap_threshold_bps,
+            reject_on_insufficient_cash: self.reject_on_insufficient_cash,
+            reject_over_position_limit: self.reject_over_position_limit,
+            allow_short: self.allow_short,
+            max_position_qty: self.max_position_qty.clone(),
+            lot_sizes: self.lot_sizes.clone(),
+            tick_sizes: self.tick_sizes.clone(),
+            max_notional_pct: self.max_notional_pct,
+            warmup_until: self.warmup_until.clone(),
+            max_participation_pct: self.max_participation_pct,
+            strict: self.strict,
+            record_calls: self.record_calls,
+            fill_timing: self.fill_timing,
+            fill_priority: self.fill_priority,
+            annual_cash_rate_bps: self.annual_cash_rate_bps,
+            annual_borrow_rate_bps: self.annual_borrow_rate_bps,
+            periods_per_year: self.periods_per_year,
+            calendar_aware: self.calendar_aware,
+            trading_calendar: self.trading_calendar.clone(),
+            fill_missing_days: self.fill_missing_days,
+            risk_free_annual_pct: self.risk_free_annual_pct,
+            omega_threshold_pct: self.omega_threshold_pct,
+            rounding: self.rounding,
+            duplicate_bars: self.duplicate_bars,
+            cost_basis: self.cost_basis,
+            leverage_limit: self.leverage_limit,
+            halt_on_blowup: self.halt_on_blowup,
+            daily_loss_limit: self.daily_loss_limit,
+            settlement_days: self.settlement_days,
+            max_drawdown_stop_pct: self.max_drawdown_stop_pct,
+            default_risk_pct: self.default_risk_pct,
+            last_bar_by_symbol: HashMap::new(),
+            prev_close: HashMap::new(),
+            bad_bars: 0,
+            duplicate_bars_seen: 0,
+            bars_seen: 0,
+            first_bar_date: String::new(),
+            last_bar_date: String::new(),
+            pending_orders: Vec::new(),
+            next_order_id: 1,
+            rejected_orders: Vec::new(),
+            expired_orders: Vec::new(),
+            truncated_orders: Vec::new(),
+            lot_adjustments: Vec::new(),
+            margin_calls: Vec::new(),
+            unsettled: Vec::new(),
+            positions: HashMap::new(),
+            entry_dates: HashMap::new(),
+            avg_cost: HashMap::new(),
+            basis: HashMap::new(),
+            open_slippage: HashMap::new(),
+            open_fees: HashMap::new(),
+            lots: HashMap::new(),
+            excursions: HashMap::new(),
+            fills: Vec::new(),
+            closed_trades: Vec::new(),
+            dividends: Vec::new(),
+            cash_flows: Vec::new(),
+            call_log: Vec::new(),
+            call_log_start: None,
+            equity_curve: Vec::new(),
+            exposure_curve: Vec::new(),
+            missing_days: Vec::new(),
+            mark_flows: Vec::new(),
+            pending_flow: 0,
+            loss_day: String::new(),
+            loss_day_equity: 0,
+            benchmark: self.benchmark.clone(),
+            symbol_currency: self.symbol_currency.clone(),
+            fx_rates: self.fx_rates.clone(),
+            realized_pnl: 0,
+            fees_paid: 0,
+            rebates: 0,
+            slippage_paid: 0,
+            interest: 0,
+            trades_closed: 0,
+            wins: 0,
+            gross_profit: 0,
+            gross_loss: 0,
+            peak_equity: self.starting_cash,
+            peak_date: String::new(),
+            max_dd: 0,
+            max_dd_days: 0,
+            blown_up_date: None,
+            drawdown_stop_date: None,
+            exposure_sum: 0.0,
+        }
+    }
+
+    /// Clear all state in place, keeping the config; see `clone_config`.
+    fn reset(&mut self, py: Python<'_>) {
+        *self = self.clone_config(py);
+    }
+
+    /// Called once per symbol per day (worker will call in a loop).
+    /// Bars that fail OHLC/volume validation raise ValueError in strict mode; otherwise they
+    /// are dropped and counted in `bad_bar_count`.
+    #[allow(clippy::too_many_arguments)]
+    fn on_bar(&mut self, date: String, symbol: String, open_paise: i64, high_paise: i64, low_paise: i64, close_paise: i64, volume: i64) -> PyResult<()> {
+        let entry = self.record("on_bar", (&date, &symbol, open_paise, high_paise, low_paise, close_paise, volume))?;
+        let result: PyResult<_> = (|| {
+            let bar = Bar { date: date.clone(), symbol: symbol.clone(), open: open_paise, high: high_paise, low: low_paise, close: close_paise, volume };
+            if let Some(problem) = bar_problem(&bar) {
+                if self.strict {
+                    return Err(PyValueError::new_err(format!("bad bar for {symbol} on {date}: {problem}")));
+                }
+                self.bad_bars += 1;
+                return Ok(());
+            }
+            let bar = match self.last_bar_by_symbol.get(&symbol) {
+                Some(prev) if prev.date == date => {
+                    if self.duplicate_bars == DuplicateBars::Reject {
+                        return Err(PyValueError::new_err(format!("duplicate bar for {symbol} on {date}")));
+                    }
+                    self.duplicate_bars_seen += 1;
+                    if self.duplicate_bars == DuplicateBars::Merge {
+                        Bar { open: prev.open, high: prev.high.max(bar.high), low: prev.low.min(bar.low), volume: prev.volume + bar.volume, ..bar }
+                    } else { bar }
+                }
+                Some(prev) => {
+                    self.prev_close.insert(symbol.clone(), prev.close);
+                    self.bars_seen += 1;
+                    bar
+                }
+                None => {
+                    self.bars_seen += 1;
+                    bar
+                }
+            };
+            self.start_loss_day(&date)?;
+            if self.first_bar_date.is_empty() || date < self.first_bar_date { self.first_bar_date = date.clone(); }
+            if date > self.last_bar_date { self.last_bar_date = date; }
+            self.last_bar_by_symbol.insert(symbol, bar);
+
+            // We only append equity point once per date; simplest approach:
+            // worker can call engine.end_of_day(date) once per date after all symbols processed.
+            // So do nothing here.
+            Ok(())
+        })();
+        self.logged(entry, result)
+    }
+
+    /// `on_bar` for a list of (date, symbol, open, high, low, close, volume) tuples in one call.
+    /// Same semantics as looping `on_bar`; in strict mode the first bad bar raises and the rows
+    /// after it are not ingested.
+    fn on_bars_batch(&mut self, rows: Vec<BarRow>) -> PyResult<()> {
+        for (date, symbol, open, high, low, close, volume) in rows {
+            self.on_bar(date, symbol, open, high, low, close, volume)?;
+        }
+        Ok(())
+    }
+
+    /// Use `fee_bps` instead of the engine-wide rate for fills in `symbol`. Applies to the
+    /// BPS and MAX fee models; flat and per-share fees are unaffected.
+    fn set_fee_bps_for_symbol(&mut self, symbol: String, fee_bps: i64) -> PyResult<()> {
+        self.record("set_fee_bps_for_symbol", (&symbol, fee_bps))?;
+        self.fee_bps_by_symbol.insert(symbol, fee_bps);
+        Ok(())
+    }
+
+    /// Price every fill with `fee_fn(symbol, side, qty, price_paise)`, which must return the
+    /// fee in paise as an int; qty is in shares (a float) and `min_fee_paise` still applies.
+    /// Replaces the fee model, maker/taker rates and per-symbol rates until cleared with
+    /// `set_fee_fn(None)`. Each fill then calls into Python, which costs far more than the
+    /// built-in models on large runs. An exception from the callback propagates and stops the
+    /// fill pass at that order: earlier fills stand, and it and the orders after it stay
+    /// pending. The callback is not part of `to_json`, so register it again after `from_json`.
+    #[pyo3(signature = (fee_fn))]
+    fn set_fee_fn(&mut self, py: Python<'_>, fee_fn: Option<PyObject>) -> PyResult<()> {
+        if let Some(f) = &fee_fn
+            && !f.bind(py).is_callable() {
+            return Err(PyValueError::new_err("fee_fn must be callable or None"));
+        }
+        self.fee_fn = fee_fn;
+        Ok(())
+    }
+
+    /// Call `bar_fn(stage, date, snapshot)` at the end of every `process_fills_for_date`
+    /// (stage "FILLS", after that date's fills, even when SameClose timing leaves nothing to
+    /// fill) and every `end_of_period` (stage "MARK", after interest, the equity mark and the
+    /// drawdown checks). `snapshot` is a dict with "cash", "equity" (the new mark for MARK,
+    /// cash plus positions at their latest closes for FILLS) and "positions" ({symbol: shares}
+    /// for open positions). The return value is ignored; an exception propagates to the
+    /// caller after the engine's own work for that call is done. With no callback (the
+    /// default, or after `set_bar_callback(None)`) nothing crosses into Python. Like the fee
+    /// callback it is not part of `to_json`.
+    #[pyo3(signature = (bar_fn))]
+    fn set_bar_callback(&mut self, py: Python<'_>, bar_fn: Option<PyObject>) -> PyResult<()> {
+        if let Some(f) = &bar_fn
+            && !f.bind(py).is_callable() {
+            return Err(PyValueError::new_err("bar_fn must be callable or None"));
+        }
+        self.bar_fn = bar_fn;
+        Ok(())
+    }
+
+    /// Seed an existing position before the first bar: `qty` shares (negative for a short)
+    /// bought at `avg_cost_paise`. Starting equity, the baseline peak for drawdowns, becomes
+    /// starting cash plus the position at cost; the first `end_of_period` marks it at that
+    /// bar's close, and returns are measured between marks from there. Raises ValueError once
+    /// a bar has been seen.
+    fn set_initial_position(&mut self, symbol: String, qty: i64, avg_cost_paise: i64) -> PyResult<()> {
+        let entry = self.record("set_initial_position", (&symbol, qty, avg_cost_paise))?;
+        let result: PyResult<_> = (|| {
+            if !self.last_bar_by_symbol.is_empty() || !self.equity_curve.is_empty() {
+                return Err(PyValueError::new_err("initial positions must be set before the first bar"));
+            }
+            let old = value_of(*self.avg_cost.get(&symbol).unwrap_or(&0), *self.positions.get(&symbol).unwrap_or(&0));
+            let q = shares(qty);
+            self.starting_equity += value_of(avg_cost_paise, q) - old;
+            self.peak_equity = self.starting_equity;
+            self.lots.remove(&symbol);
+            if q == 0 {
+                self.positions.remove(&symbol);
+                self.avg_cost.remove(&symbol);
+                self.basis.remove(&symbol);
+                self.entry_dates.remove(&symbol);
+                self.excursions.remove(&symbol);
+                return Ok(());
+            }
+            if self.cost_basis != CostBasis::Average {
+                self.lots.insert(symbol.clone(), vec![Lot { qty: q, price: avg_cost_paise, date: String::new() }]);
+            }
+            self.positions.insert(symbol.clone(), q);
+            self.avg_cost.insert(symbol.clone(), avg_cost_paise);
+            self.basis.insert(symbol.clone(), value_of(avg_cost_paise, q.abs()));
+            self.excursions.insert(symbol.clone(), (avg_cost_paise, avg_cost_paise));
+            self.entry_dates.insert(symbol, String::new());
+            Ok(())
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Cap the absolute position in `symbol` at `qty` shares, long or short.
+    fn set_max_position_qty(&mut self, symbol: String, qty: i64) -> PyResult<()> {
+        self.record("set_max_position_qty", (&symbol, qty))?;
+        self.max_position_qty.insert(symbol, shares(qty));
+        Ok(())
+    }
+
+    /// Trade `symbol` in multiples of `lot` shares. On its first fill attempt an order is
+    /// rounded down to whole lots, the odd lot being dropped (see `lot_adjustments`), or
+    /// rejected as "BELOW_LOT_SIZE" if smaller than one lot; partial fills from participation
+    /// and position limits are rounded down to whole lots too. Raises ValueError unless `lot`
+    /// is positive.
+    fn set_lot_size(&mut self, symbol: String, lot: i64) -> PyResult<()> {
+        let entry = self.record("set_lot_size", (&symbol, lot))?;
+        let result: PyResult<_> = (|| {
+            if lot <= 0 {
+                return Err(PyValueError::new_err(format!("lot size must be positive, got {lot}")));
+            }
+            self.lot_sizes.insert(symbol, shares(lot));
+            Ok(())
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Round fill prices in `symbol` to multiples of `tick_paise`, in the symbol's currency,
+    /// after slippage and always against the trade: a BUY rounds up to the next tick and a SELL
+    /// down, so the rounding is a cost and counts in `slippage_paise`. Limit fills never round
+    /// through the limit. Raises ValueError unless `tick_paise` is positive.
+    fn set_tick_size(&mut self, symbol: String, tick_paise: Money) -> PyResult<()> {
+        let entry = self.record("set_tick_size", (&symbol, tick_paise))?;
+        let result: PyResult<_> = (|| {
+            if tick_paise <= 0 {
+                return Err(PyValueError::new_err(format!("tick size must be positive, got {tick_paise}")));
+            }
+            self.tick_sizes.insert(symbol, tick_paise);
+            Ok(())
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Cap every position's absolute value at `pct` percent of equity, both marked at the
+    /// fill price and last closes when the fill happens. None removes the cap.
+    fn set_max_notional_pct(&mut self, pct: Option<f64>) -> PyResult<()> {
+        self.record("set_max_notional_pct", (pct,))?;
+        self.max_notional_pct = pct;
+        Ok(())
+    }
+
+    /// Trailing stop: a SELL tracks the highest price since placement and triggers when the bar
+    /// trades `trail_bps` below it; a BUY tracks the lowest price and triggers `trail_bps` above.
+    /// The watermark starts at the symbol's last close and ratchets with each bar's open (before
+    /// the trigger check) and high/low (after it); it never moves back. Fills like a stop.
+    #[pyo3(signature = (symbol, side, qty, trail_bps, tif="GTC"))]
+    fn place_trailing_stop(&mut self, symbol: String, side: String, qty: i64, trail_bps: i64, tif: &str) -> PyResult<i64> {
+        let entry = self.record("place_trailing_stop", (&symbol, &side, qty, trail_bps, tif))?;
+        let result: PyResult<_> = (|| {
+            check_order(&side, shares(qty), tif)?;
+            let extreme = self.last_bar_by_symbol.get(&symbol).map(|b| b.close);
+            self.queue_order(symbol, side, shares(qty), OrderType::TrailingStop { trail_bps, extreme }, tif)
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Bracket: a market entry that, once filled, attaches an OCO exit pair for the filled qty —
+    /// a stop at `stop_paise` and a limit at `target_paise` on the opposite side. The exits are
+    /// live from the next bar; when one fills the other is cancelled. If both could fill on the
+    /// same bar the stop wins. fills() reports the exit as leg "STOP" or "TARGET".
+    #[pyo3(signature = (symbol, side, qty, stop_paise, target_paise, tif="GTC"))]
+    fn place_bracket(&mut self, symbol: String, side: String, qty: i64, stop_paise: i64, target_paise: i64, tif: &str) -> PyResult<i64> {
+        let entry = self.record("place_bracket", (&symbol, &side, qty, stop_paise, target_paise, tif))?;
+        let result: PyResult<_> = (|| {
+            check_order(&side, shares(qty), tif)?;
+            let mut order = self.new_order(symbol, side, shares(qty), OrderType::Market, tif);
+            order.bracket = Some(Bracket { stop: stop_paise, target: target_paise });
+            self.admit(order)
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Strategy calls this through ctx.buy/sell.
+    /// `tif` (all place_* methods): "GTC" keeps the order pending until it fills; "DAY" expires
+    /// whatever didn't fill on the first bar for its symbol, see `expired_orders`.
+    /// Direction comes only from `side`: every place_* method raises ValueError unless side
+    /// is "BUY" or "SELL" and qty is positive (fractional qty must round to at least 0.001).
+    /// `timing`: "MOO" fills this order at the open of the bar `process_fills_for_date` fills it
+    /// on and "MOC" at that bar's close, whatever `fill_timing` says, so entries and exits can
+    /// use different references. None follows `fill_timing`. Raises ValueError for any other
+    /// value, or under SameClose timing, where fills happen at `end_of_day` instead.
+    #[pyo3(signature = (symbol, side, qty, tif="GTC", timing=None))]
+    fn place_market_order(&mut self, symbol: String, side: String, qty: i64, tif: &str, timing: Option<String>) -> PyResult<i64> {
+        let entry = self.record("place_market_order", (&symbol, &side, qty, tif, &timing))?;
+        let result: PyResult<_> = (|| {
+            check_order(&side, shares(qty), tif)?;
+            if let Some(t) = &timing {
+                if t != "MOO" && t != "MOC" {
+                    return Err(PyValueError::new_err(format!("timing must be MOO or MOC, got {t}")));
+                }
+                if self.fill_timing == FillTiming::SameClose {
+                    return Err(PyValueError::new_err("per-order timing needs NextOpen or NextClose fill_timing"));
+                }
+            }
+            let mut order = self.new_order(symbol, side, shares(qty), OrderType::Market, tif);
+            order.timing = timing;
+            self.admit(order)
+        })();
+        self.logged(entry, result)
+    }
+
+    /// (fill_price, fee) a market order for `qty` shares would get if it filled on the
+    /// symbol's last bar: its open under NextOpen timing, its close otherwise, with base and
+    /// impact slippage, tick rounding, the fee model and FX conversion as for a real fill. Gap
+    /// slippage, participation and position limits depend on the bar the order actually fills
+    /// on and are left out, so under NextOpen this is an estimate unless that bar opens at the
+    /// same price.
+    /// Changes nothing. Raises ValueError with no bar for the symbol, or where a fill would be
+    /// rejected as ZERO_VOLUME.
+    fn preview_fill(&self, symbol: String, side: String, qty: i64) -> PyResult<(Money, Money)> {
+        check_order(&side, shares(qty), "GTC")?;
+        let Some(bar) = self.last_bar_by_symbol.get(&symbol) else {
+            return Err(PyValueError::new_err(format!("no bar for {symbol}")));
+        };
+        let Some(slippage_bps) = self.slippage_bps_for(bar.volume, shares(qty)) else {
+            return Err(PyValueError::new_err(format!("{symbol} has no volume to price impact on {}", bar.date)));
+        };
+        let reference = if self.fill_timing == FillTiming::NextOpen { bar.open } else { bar.close };
+        let px = apply_slippage(reference, slippage_bps, self.slippage_model.per_share(), &side);
+        let order = PendingOrder {
+            id: 0, symbol, side, qty: shares(qty), kind: OrderType::Market,
+            bracket: None, oco_with: None, leg: None, tif: "GTC".to_string(), timing: None, fees: None,
+        };
+        let px = self.to_base(&order.symbol, &bar.date, self.tick_round(&order, px))?;
+        let fee = self.fill_fee(&order, order.qty, px, value_of(px, order.qty))?;
+        Ok((px, fee))
+    }
+
+    /// Market order that moves the position in `symbol` to `pct` percent of current equity
+    /// (long for BUY, short for SELL), in whole shares at the last close; see
+    /// `order_target_notional`.
+    fn order_target_percent(&mut self, symbol: String, side: String, pct: f64) -> PyResult<f64> {
+        let equity = self.current_equity()?;
+        self.order_target_notional(symbol, side, (equity as f64 * pct / 100.0) as Money)
+    }
+
+    /// Market order that moves the position in `symbol` to `notional_paise` worth of shares
+    /// (long for BUY, short for SELL) at the last close, truncated to whole shares. Accounts for
+    /// the current position, fractional shares included, but not for orders still pending.
+    /// Returns the signed share delta ordered (positive buys, negative sells); 0 places nothing.
+    /// Raises ValueError for a side other than BUY or SELL, or if no bar has been seen for the
+    /// symbol.
+    fn order_target_notional(&mut self, symbol: String, side: String, notional_paise: i64) -> PyResult<f64> {
+        let entry = self.record("order_target_notional", (&symbol, &side, notional_paise))?;
+        let result: PyResult<_> = (|| {
+            check_side(&side)?;
+            let close = match self.last_bar_by_symbol.get(&symbol) {
+                Some(bar) if bar.close > 0 => self.to_base(&symbol, &bar.date, bar.close)?,
+                _ => return Err(PyValueError::new_err(format!("no price for {symbol}"))),
+            };
+            let target = shares(notional_paise / close) * if side == "SELL" { -1 } else { 1 };
+            let delta = target - self.positions.get(&symbol).copied().unwrap_or(0);
+            if delta != 0 {
+                let side = if delta > 0 { "BUY" } else { "SELL" };
+                self.queue_order(symbol, side.to_string(), delta.abs(), OrderType::Market, "GTC")?;
+            }
+            Ok(qty_to_f64(delta))
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Market order for a fractional number of shares, rounded to 0.001 share.
+    #[pyo3(signature = (symbol, side, qty, tif="GTC"))]
+    fn place_market_order_fractional(&mut self, symbol: String, side: String, qty: f64, tif: &str) -> PyResult<i64> {
+        let entry = self.record("place_market_order_fractional", (&symbol, &side, qty, tif))?;
+        let result: PyResult<_> = (|| {
+            check_order(&side, shares_f64(qty), tif)?;
+            self.queue_order(symbol, side, shares_f64(qty), OrderType::Market, tif)
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Limit order: BUY fills when the bar trades at or below the limit, SELL at or above.
+    /// Fills at the limit or the open, whichever is better; no slippage is applied.
+    /// Stays pending across days until the limit is reached.
+    #[pyo3(signature = (symbol, side, qty, limit_paise, tif="GTC"))]
+    fn place_limit_order(&mut self, symbol: String, side: String, qty: i64, limit_paise: i64, tif: &str) -> PyResult<i64> {
+        let entry = self.record("place_limit_order", (&symbol, &side, qty, limit_paise, tif))?;
+        let result: PyResult<_> = (|| {
+            check_order(&side, shares(qty), tif)?;
+            self.queue_order(symbol, side, shares(qty), OrderType::Limit(limit_paise), tif)
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Stop order: a SELL stop triggers when the bar trades at or below the stop, a BUY stop
+    /// at or above. Once triggered it fills like a market order at the stop, or at the open
+    /// if the bar gapped through it, plus slippage. Stays pending until triggered.
+    #[pyo3(signature = (symbol, side, qty, stop_paise, tif="GTC"))]
+    fn place_stop_order(&mut self, symbol: String, side: String, qty: i64, stop_paise: i64, tif: &str) -> PyResult<i64> {
+        let entry = self.record("place_stop_order", (&symbol, &side, qty, stop_paise, tif))?;
+        let result: PyResult<_> = (|| {
+            check_order(&side, shares(qty), tif)?;
+            self.queue_order(symbol, side, shares(qty), OrderType::Stop(stop_paise), tif)
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Queue a market order flattening every open position (SELL longs, BUY back shorts), to
+    /// fill on the next `process_fills_for_date`. Returns the order ids, by symbol.
+    fn liquidate_all(&mut self) -> PyResult<Vec<i64>> {
+        self.record("liquidate_all", ())?;
+        Ok(self.flatten_positions())
+    }
+
+    /// Drop a pending order. Returns false if no pending order has `order_id` (already
+    /// filled, rejected, cancelled or never placed). Cancelling one bracket exit leaves the other.
+    fn cancel_order(&mut self, order_id: i64) -> PyResult<bool> {
+        self.record("cancel_order", (order_id,))?;
+        let before = self.pending_orders.len();
+        self.pending_orders.retain(|o| o.id != order_id);
+        Ok(self.pending_orders.len() < before)
+    }
+
+    /// Drop every pending order for `symbol`; returns how many were cancelled.
+    fn cancel_all_for_symbol(&mut self, symbol: String) -> PyResult<usize> {
+        self.record("cancel_all_for_symbol", (&symbol,))?;
+        let before = self.pending_orders.len();
+        self.pending_orders.retain(|o| o.symbol != symbol);
+        Ok(before - self.pending_orders.len())
+    }
+
+    /// Execute fills on NEXT_OPEN using next day's open, so worker should call this at the *start* of day
+    /// after loading bars for that date (bars already set via on_bar). With NextClose timing the same
+    /// call fills at the day's close instead; with SameClose it does nothing and `end_of_day` fills.
+    fn process_fills_for_date(&mut self, date: String) -> PyResult<()> {
+        let entry = self.record("process_fills_for_date", (&date,))?;
+        let result: PyResult<_> = (|| {
+            self.start_loss_day(&date)?;
+            if self.fill_timing != FillTiming::SameClose {
+                self.fill_pending_orders(&date)?;
+            }
+            if self.bar_fn.is_some() {
+                self.run_bar_fn("FILLS", &date, self.current_equity()?)?;
+            }
+            Ok(())
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Benchmark closes for the relative metrics, replacing any previous benchmark. Returns are
+    /// compared only between consecutive equity marks whose dates both have a benchmark close.
+    fn set_benchmark(&mut self, dates: Vec<String>, closes: Vec<Money>) -> PyResult<()> {
+        let entry = self.record("set_benchmark", (&dates, &closes))?;
+        let result: PyResult<_> = (|| {
+            if dates.len() != closes.len() {
+                return Err(PyValueError::new_err(format!("{} benchmark dates but {} closes", dates.len(), closes.len())));
+            }
+            self.benchmark = dates.into_iter().zip(closes).collect();
+            Ok(())
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Leave everything dated before `date` out of `metrics()`, e.g. an indicator warmup. Its
+    /// return series starts at the first equity mark on or after `date`, which stands in for
+    /// starting equity, and only trades closed, fills and interest from then on count.
+    /// `equity_curve()` and the other accessors keep the whole run. None removes the cutoff.
+    fn set_warmup_until(&mut self, date: Option<String>) -> PyResult<()> {
+        self.record("set_warmup_until", (&date,))?;
+        self.warmup_until = date;
+        Ok(())
+    }
+
+    /// Quote `symbol` in `currency` (None for the base currency). Bars, limit and stop prices
+    /// and dividends for it stay in that currency; fills are converted to base at the rate in
+    /// force that day (see `set_fx_rate`), so cash, fees, avg cost, PnL and equity are all in
+    /// base. Raises ValueError while a position in `symbol` is open.
+    #[pyo3(signature = (symbol, currency))]
+    fn set_symbol_currency(&mut self, symbol: String, currency: Option<String>) -> PyResult<()> {
+        let entry = self.record("set_symbol_currency", (&symbol, &currency))?;
+        let result: PyResult<_> = (|| {
+            if *self.positions.get(&symbol).unwrap_or(&0) != 0 {
+                return Err(PyValueError::new_err(format!("cannot change the currency of open position {symbol}")));
+            }
+            match currency {
+                Some(ccy) => self.symbol_currency.insert(symbol, ccy),
+                None => self.symbol_currency.remove(&symbol),
+            };
+            Ok(())
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Value of one unit of `currency` in base currency from `date` on, in bps (83.25 is
+    /// 832500). The latest rate on or before a date applies; converting a symbol in a currency
+    /// with no such rate raises ValueError.
+    fn set_fx_rate(&mut self, currency: String, date: String, rate_to_base_bps: i64) -> PyResult<()> {
+        let entry = self.record("set_fx_rate", (&currency, &date, rate_to_base_bps))?;
+        let result: PyResult<_> = (|| {
+            if rate_to_base_bps <= 0 {
+                return Err(PyValueError::new_err(format!("FX rate must be positive, got {rate_to_base_bps}")));
+            }
+            self.fx_rates.entry(currency).or_default().insert(date, rate_to_base_bps);
+            Ok(())
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Cash distribution of `per_share_paise` (in the symbol's currency) on the position held
+    /// in `symbol`: longs are credited, shorts pay it. Kept out of realized PnL; see
+    /// `dividends_received`.
+    fn apply_dividend(&mut self, symbol: String, date: String, per_share_paise: i64) -> PyResult<()> {
+        let entry = self.record("apply_dividend", (&symbol, &date, per_share_paise))?;
+        let result: PyResult<_> = (|| {
+            let q = *self.positions.get(&symbol).unwrap_or(&0);
+            if q == 0 { return Ok(()); }
+            let amount = value_of(self.to_base(&symbol, &date, per_share_paise)?, q);
+            self.post_cash(&date, "DIVIDEND", amount);
+            self.dividends.push((date, symbol, amount));
+            Ok(())
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Split of `ratio_num`:`ratio_den` (2:1 doubles the share count). Scales the position and
+    /// divides the avg cost so the total basis is unchanged, rounding avg cost to the nearest paisa.
+    /// The symbol's last bar and previous close are restated in post-split terms (volume scaled
+    /// like shares), so equity, sizing and gap slippage see no jump before the next bar. Its
+    /// pending orders are scaled the same way, qty up and limit, stop, trailing watermark and
+    /// bracket prices down; an order scaled to nothing is dropped.
+    fn apply_split(&mut self, symbol: String, ratio_num: i64, ratio_den: i64) -> PyResult<()> {
+        let entry = self.record("apply_split", (&symbol, ratio_num, ratio_den))?;
+        let result: PyResult<_> = (|| {
+            if ratio_num <= 0 || ratio_den <= 0 {
+                return Err(PyValueError::new_err("split ratio must be positive"));
+            }
+            let rescale = |px: Money| split_price(px, ratio_num, ratio_den);
+            if let Some(bar) = self.last_bar_by_symbol.get_mut(&symbol) {
+                for px in [&mut bar.open, &mut bar.high, &mut bar.low, &mut bar.close] { *px = rescale(*px); }
+                bar.volume = bar.volume.saturating_mul(ratio_num) / ratio_den;
+            }
+            if let Some(close) = self.prev_close.get_mut(&symbol) { *close = rescale(*close); }
+            for order in self.pending_orders.iter_mut().filter(|o| o.symbol == symbol) {
+                order.qty = order.qty.saturating_mul(ratio_num) / ratio_den;
+                order.kind = order.kind.split(ratio_num, ratio_den);
+                if let Some(b) = order.bracket.as_mut() {
+                    b.stop = rescale(b.stop);
+                    b.target = rescale(b.target);
+                }
+            }
+            self.pending_orders.retain(|o| o.symbol != symbol || o.qty > 0);
+            if let Some((lo, hi)) = self.excursions.get_mut(&symbol) {
+                // keep the seen range in post-split prices
+                *lo = rescale(*lo);
+                *hi = rescale(*hi);
+            }
+
+            let q = *self.positions.get(&symbol).unwrap_or(&0);
+            if q == 0 { return Ok(()); }
+            let avg = *self.avg_cost.get(&symbol).unwrap_or(&0);
+            let basis = avg.saturating_mul(q.abs());
+            let new_q = q.saturating_mul(ratio_num) / ratio_den;
+            let new_avg = if new_q != 0 { (basis + new_q.abs() / 2) / new_q.abs() } else { 0 };
+            self.positions.insert(symbol.clone(), new_q);
+            self.avg_cost.insert(symbol.clone(), new_avg);
+            if let Some(lots) = self.lots.get_mut(&symbol) {
+                // Same rounding per lot; the last lot absorbs the share rounding so lots sum to new_q
+                for lot in lots.iter_mut() {
+                    let basis = lot.price.saturating_mul(lot.qty.abs());
+                    lot.qty = lot.qty.saturating_mul(ratio_num) / ratio_den;
+                    lot.price = if lot.qty != 0 { (basis + lot.qty.abs() / 2) / lot.qty.abs() } else { 0 };
+                }
+                let drift = new_q - lots.iter().map(|l| l.qty).sum::<Qty>();
+                if let Some(last) = lots.last_mut() { last.qty += drift; }
+            }
+            Ok(())
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Daily-bar name for `end_of_period`.
+    fn end_of_day(&mut self, date: String) -> PyResult<()> {
+        self.end_of_period(date)
+    }
+
+    /// Accrue a period of interest on cash, then mark the equity point for `date` (a day or an
+    /// intraday timestamp): cash + sum(pos * close), where shorts count negative. With SameClose
+    /// timing, pending orders are filled at this bar's close first.
+    fn end_of_period(&mut self, date: String) -> PyResult<()> {
+        let entry = self.record("end_of_period", (&date,))?;
+        let result: PyResult<_> = (|| {
+            // Everything that can refuse the mark is checked before the first change, so a raise
+            // leaves the engine as it was and the call can be retried
+            if let Some(calendar) = &self.trading_calendar && calendar.binary_search(&date).is_err() {
+                return Err(PyValueError::new_err(format!("{date} is not in the trading calendar")));
+            }
+            if self.fill_timing == FillTiming::SameClose {
+                self.check_fillable(&date)?;
+            }
+            // SameClose fills need a bar, so they can't close a position that has none
+            if self.strict && let Some((sym, _)) = self.positions.iter().find(|(sym, q)| **q != 0 && !self.has_bar(sym, &date)) {
+                return Err(PyValueError::new_err(format!("open position in {sym} has no bar on {date}")));
+            }
+            if let Some(calendar) = &self.trading_calendar && let Some((last, _)) = self.equity_curve.last() {
+                let from = calendar.partition_point(|d| d <= last);
+                let to = calendar.partition_point(|d| *d < date);
+                let skipped = calendar.get(from..to).unwrap_or_default().to_vec();
+                for day in skipped {
+                    if self.fill_missing_days { self.carry_mark(&day); }
+                    self.missing_days.push(day);
+                }
+            }
+            if self.fill_timing == FillTiming::SameClose {
+                self.fill_pending_orders(&date)?;
+            }
+            for (left, _) in self.unsettled.iter_mut() { *left -= 1; }
+            self.unsettled.retain(|(left, _)| *left > 0);
+
+            let rate_bps = if self.cash >= 0 { self.annual_cash_rate_bps } else { self.annual_borrow_rate_bps };
+            let accrued = (self.cash as f64 * rate_bps as f64 / (10_000.0 * self.periods_per_year)) as Money;
+            self.post_cash(&date, "INTEREST", accrued);
+            self.interest += accrued;
+
+            let mut equity = self.cash;
+            let mut gross: Money = 0;
+            let mut net: Money = 0;
+            for (sym, q) in self.positions.iter() {
+                if *q == 0 { continue; }
+                if let Some(bar) = self.last_bar_by_symbol.get(sym)
+                    && bar.date == date {
+                    let close = self.to_base(sym, &date, bar.close)?;
+                    equity += value_of(close, *q);
+                    net += value_of(close, *q);
+                    gross += value_of(close, *q).abs();
+                    Self::touch_excursion(&mut self.excursions, sym, close);
+                }
+            }
+            if equity > 0 { self.exposure_sum += gross as f64 / equity as f64; }
+            if let Some(limit) = self.leverage_limit
+                && gross > 0 && (equity <= 0 || gross as f64 > limit * equity as f64) {
+                self.margin_calls.push((date.clone(), gross, equity));
+            }
+            if equity <= 0 && self.blown_up_date.is_none() {
+                self.blown_up_date = Some(date.clone());
+            }
+            let was_underwater = self.equity_curve.last().is_some_and(|(_, e)| *e < self.peak_equity);
+            self.equity_curve.push((date.clone(), equity));
+            self.exposure_curve.push((date.clone(), net, gross));
+            let flow = std::mem::take(&mut self.pending_flow);
+            self.mark_flows.push(flow);
+            self.track_drawdown(&date, equity, flow, was_underwater);
+
+            if let Some(stop_pct) = self.max_drawdown_stop_pct
+                && self.drawdown_stop_date.is_none() && self.current_drawdown_pct() > stop_pct {
+                self.drawdown_stop_date = self.equity_curve.last().map(|(d, _)| d.clone());
+                self.pending_orders.clear();
+                self.flatten_positions();
+            }
+            if let Some((date, equity)) = self.equity_curve.last() {
+                self.run_bar_fn("MARK", date, *equity)?;
+            }
+            Ok(())
+        })();
+        self.logged(entry, result)
     }
 
     fn cash(&self) -> i64 { self.cash }
 
+    /// Add `amount_paise` of capital to cash on `date`, logged as DEPOSIT in `cash_flows`.
+    /// It raises equity from the next mark without counting as a return: each mark's return
+    /// is taken against the previous mark plus the capital moved in between, and the peak
+    /// for drawdowns moves by the same amount. Raises ValueError unless the amount is positive.
+    fn deposit(&mut self, date: String, amount_paise: i64) -> PyResult<()> {
+        let entry = self.record("deposit", (&date, amount_paise))?;
+        let result: PyResult<_> = (|| {
+            if amount_paise <= 0 {
+                return Err(PyValueError::new_err(format!("deposit must be positive, got {amount_paise}")));
+            }
+            self.post_cash(&date, "DEPOSIT", amount_paise);
+            self.pending_flow += amount_paise;
+            self.loss_day_equity += amount_paise;
+            Ok(())
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Take `amount_paise` of capital out of cash on `date`, logged as WITHDRAWAL; the
+    /// reverse of `deposit`. Cash may go negative, as for a buy. Raises ValueError unless the
+    /// amount is positive.
+    fn withdraw(&mut self, date: String, amount_paise: i64) -> PyResult<()> {
+        let entry = self.record("withdraw", (&date, amount_paise))?;
+        let result: PyResult<_> = (|| {
+            if amount_paise <= 0 {
+                return Err(PyValueError::new_err(format!("withdrawal must be positive, got {amount_paise}")));
+            }
+            self.post_cash(&date, "WITHDRAWAL", -amount_paise);
+            self.pending_flow -= amount_paise;
+            self.loss_day_equity -= amount_paise;
+            Ok(())
+        })();
+        self.logged(entry, result)
+    }
+
+    /// Sale proceeds included in `cash` that haven't settled yet; see `settlement_days`.
+    fn unsettled_cash(&self) -> Money {
+        self.unsettled.iter().map(|(_, amount)| amount).sum()
+    }
+
+    fn bad_bar_count(&self) -> i64 { self.bad_bars }
+
+    fn duplicate_bar_count(&self) -> i64 { self.duplicate_bars_seen }
+
+    /// Bars `on_bar` has taken in, counting each (symbol, date) once: neither bad bars nor
+    /// repeats of a bar already seen add to it.
+    fn bars_seen(&self) -> i64 { self.bars_seen }
+
+    /// Every symbol with an accepted bar, sorted.
+    fn symbols_seen(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = self.last_bar_by_symbol.keys().cloned().collect();
+        symbols.sort();
+        symbols
+    }
+
+    /// (earliest, latest) date of an accepted bar, compared as strings; ("", "") before any.
+    fn date_range(&self) -> (String, String) {
+        (self.first_bar_date.clone(), self.last_bar_date.clone())
+    }
+
+    fn periods_per_year(&self) -> f64 { self.periods_per_year }
+
+    /// Marks per year the metrics annualize by: `periods_per_year`, or with `calendar_aware`
+    /// the marks per calendar year of the equity curve so far.
+    fn metric_periods_per_year(&self) -> f64 {
+        if !self.calendar_aware { return self.periods_per_year; }
+        match (self.equity_curve.first(), self.equity_curve.last()) {
+            (Some((start, _)), Some((end, _))) if self.equity_curve.len() >= 2 => {
+                match (day_number(start), day_number(end)) {
+                    (Some(a), Some(b)) if b > a => (self.equity_curve.len() - 1) as f64 * 365.25 / (b - a) as f64,
+                    _ => self.periods_per_year,
+                }
+            }
+            _ => self.periods_per_year,
+        }
+    }
+
+    /// Date of the first equity mark at or below zero, None if the run never went bust.
+    fn blown_up_date(&self) -> Option<String> { self.blown_up_date.clone() }
+
+    /// Date of the mark that tripped `max_drawdown_stop_pct`, None if it never fired.
+    fn drawdown_stop_date(&self) -> Option<String> { self.drawdown_stop_date.clone() }
+
+    /// Highest equity marked so far, starting equity before the first mark.
+    fn peak_equity(&self) -> Money { self.peak_equity }
+
+    /// Date of the last mark at `peak_equity`, "" before the first mark.
+    fn peak_date(&self) -> String { self.peak_date.clone() }
+
+    /// Percent the latest equity mark is below `peak_equity`, 0.0 at a peak or with no marks.
+    fn current_drawdown_pct(&self) -> f64 {
+        match self.equity_curve.last() {
+            Some((_, e)) if self.peak_equity > 0 => (self.peak_equity - e) as f64 / self.peak_equity as f64 * 100.0,
+            _ => 0.0,
+        }
+    }
+
+    /// Position in whole shares, truncated toward zero; see `position_fractional`.
     fn position(&self, symbol: String) -> i64 {
-        *self.positions.get(&symbol).unwrap_or(&0)
+        *self.positions.get(&symbol).unwrap_or(&0) / QTY_SCALE
+    }
+
+    fn position_fractional(&self, symbol: String) -> f64 {
+        qty_to_f64(*self.positions.get(&symbol).unwrap_or(&0))
+    }
+
+    /// Avg cost per share of the open position in `symbol` (the lots' weighted avg under
+    /// FIFO/LIFO cost basis), 0 when flat.
+    fn avg_cost(&self, symbol: String) -> Money {
+        if *self.positions.get(&symbol).unwrap_or(&0) == 0 { return 0; }
+        *self.avg_cost.get(&symbol).unwrap_or(&0)
+    }
+
+    /// Mark-to-market PnL of open positions at `date`: sum(close * qty) less their cost basis.
+    /// Together with realized PnL it accounts for every paisa the positions made or lost.
+    /// Raises ValueError if an open position has no bar for `date`.
+    fn unrealized_pnl(&self, date: String) -> PyResult<Money> {
+        let mut total: Money = 0;
+        for (sym, q) in self.positions.iter() {
+            if *q == 0 { continue; }
+            let bar = match self.last_bar_by_symbol.get(sym) {
+                Some(b) if b.date == date => b,
+                _ => return Err(PyValueError::new_err(format!("no bar for {sym} on {date}"))),
+            };
+            let basis = *self.basis.get(sym).unwrap_or(&0);
+            total += value_of(self.to_base(sym, &date, bar.close)?, *q) - basis * q.signum();
+        }
+        Ok(total)
+    }
+
+    /// Every open position at `date`, by symbol: (symbol, qty, avg_cost, market_value), with
+    /// avg_cost as `avg_cost` and market value at the day's close (negative when short).
+    /// Raises ValueError like `unrealized_pnl` if an open position has no bar for `date`.
+    fn portfolio_snapshot(&self, date: String) -> PyResult<Vec<(String, f64, Money, Money)>> {
+        let mut rows = Vec::new();
+        for (sym, q) in self.positions.iter() {
+            if *q == 0 { continue; }
+            let bar = match self.last_bar_by_symbol.get(sym) {
+                Some(b) if b.date == date => b,
+                _ => return Err(PyValueError::new_err(format!("no bar for {sym} on {date}"))),
+            };
+            let avg = *self.avg_cost.get(sym).unwrap_or(&0);
+            rows.push((sym.clone(), qty_to_f64(*q), avg, value_of(self.to_base(sym, &date, bar.close)?, *q)));
+        }
+        rows.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(rows)
+    }
+
+    /// Market value of the position at the symbol's most recent close (negative when short).
+    /// Raises ValueError if the position is open but no bar has been seen for the symbol.
+    fn position_value(&self, symbol: String) -> PyResult<Money> {
+        let q = *self.positions.get(&symbol).unwrap_or(&0);
+        if q == 0 { return Ok(0); }
+        match self.last_bar_by_symbol.get(&symbol) {
+            Some(bar) => Ok(value_of(self.to_base(&symbol, &bar.date, bar.close)?, q)),
+            None => Err(PyValueError::new_err(format!("no bar for {symbol}"))),
+        }
+    }
+
+    /// Cash plus every open position at its symbol's last close, without recording an equity
+    /// mark or touching drawdown state. Raises ValueError like `position_value`.
+    fn current_equity(&self) -> PyResult<Money> {
+        let mut equity = self.cash;
+        for sym in self.positions.keys() {
+            equity += self.position_value(sym.clone())?;
+        }
+        Ok(equity)
     }
 
     fn equity_curve(&self) -> Vec<(String, i64)> {
         self.equity_curve.clone()
     }
 
-    fn fills(&self) -> Vec<(String, String, String, i64, i64, i64, i64)> {
-        // (date, symbol, side, qty, price, fee, order_id)
-        self.fills.iter().map(|f| (f.date.clone(), f.symbol.clone(), f.side.clone(), f.qty, f.price, f.fee, f.order_id)).collect()
+    /// Trading calendar dates passed over between two `end_of_period` marks, in the order
+    /// they were found; see `trading_calendar`. Empty without a calendar.
+    fn missing_days(&self) -> Vec<String> {
+        self.missing_days.clone()
+    }
+
+    /// Position value at each `equity_curve()` point: (date, net, gross), net being the signed
+    /// sum of position values at the mark's closes (shorts negative) and gross the sum of their
+    /// absolute values. A dollar-neutral book keeps net near 0.
+    ///
+    /// There is no beta-weighted net: that needs each symbol's beta to the benchmark, and the
+    /// engine keeps only the latest bar per symbol, not a return history to estimate it from.
+    /// Scaling net by the strategy's own beta (see `Metrics::beta`) would read 0 for a
+    /// dollar-neutral book long high-beta names and short low-beta ones. Weight each
+    /// `portfolio_snapshot` row by a beta estimated from the bars fed in instead.
+    fn exposure_curve(&self) -> Vec<(String, Money, Money)> {
+        self.exposure_curve.clone()
+    }
+
+    /// Underwater curve: percent below the running peak at each equity mark, one entry per
+    /// `equity_curve()` point. The peak starts at starting equity, as for max drawdown, and
+    /// moves with deposits and withdrawals.
+    fn drawdown_curve(&self) -> Vec<(String, f64)> {
+        let mut peak = self.starting_equity;
+        self.equity_curve.iter().zip(&self.mark_flows).map(|((date, e), flow)| {
+            peak = (peak + flow).max(*e);
+            let dd = if peak > 0 { (peak - e) as f64 / peak as f64 * 100.0 } else { 0.0 };
+            (date.clone(), dd)
+        }).collect()
+    }
+
+    /// Percent the strategy/benchmark ratio is below its running peak, one entry per aligned
+    /// return as in the benchmark metrics, dated at the later mark. The ratio starts at 1 and
+    /// compounds the strategy's return, net of deposits and withdrawals, over the benchmark's
+    /// between consecutive marks that both have a benchmark close. Empty without a benchmark.
+    fn relative_drawdown_curve(&self) -> Vec<(String, f64)> {
+        let (mut ratio, mut peak) = (1.0_f64, 1.0_f64);
+        let mut out = Vec::new();
+        for i in 1..self.equity_curve.len() {
+            let (prev_date, _) = &self.equity_curve[i - 1];
+            let (date, cur) = &self.equity_curve[i];
+            if let (Some(&b0), Some(&b1), Some(prev)) = (self.benchmark.get(prev_date), self.benchmark.get(date), self.mark_base(i))
+                && prev > 0 && *cur > 0 && b0 > 0 && b1 > 0
+            {
+                ratio *= (*cur as f64 * b0 as f64) / (prev as f64 * b1 as f64);
+                peak = peak.max(ratio);
+                out.push((date.clone(), (peak - ratio) / peak * 100.0));
+            }
+        }
+        out
+    }
+
+    /// Drawdown episodes at least `threshold_pct` deep, from `drawdown_curve()`: (start, trough,
+    /// recovery, depth_pct) per run of marks below the running peak. start is the last mark at
+    /// the peak (the first mark if the run begins there, starting equity being the peak),
+    /// trough the deepest mark, recovery the first mark back at the peak or "ongoing", and
+    /// depth_pct the percent below peak at the trough.
+    fn drawdown_episodes(&self, threshold_pct: f64) -> Vec<(String, String, String, f64)> {
+        let curve = self.drawdown_curve();
+        let mut out = Vec::new();
+        let mut open: Option<(String, String, f64)> = None; // (start, trough, depth)
+        for (i, (date, dd)) in curve.iter().enumerate() {
+            if *dd > 0.0 {
+                let (_, trough, depth) = open.get_or_insert_with(|| {
+                    let start = if i == 0 { date.clone() } else { curve[i - 1].0.clone() };
+                    (start, date.clone(), 0.0)
+                });
+                if *dd > *depth {
+                    *depth = *dd;
+                    *trough = date.clone();
+                }
+            } else if let Some((start, trough, depth)) = open.take() {
+                out.push((start, trough, date.clone(), depth));
+            }
+        }
+        if let Some((start, trough, depth)) = open {
+            out.push((start, trough, "ongoing".to_string(), depth));
+        }
+        out.retain(|e| e.3 >= threshold_pct);
+        out
+    }
+
+    /// Historical (VaR, CVaR) at `confidence` (e.g. 0.95) over per-period log returns, as
+    /// positive percentages of equity: VaR is the loss at the (1 - confidence) quantile, CVaR
+    /// the mean return at or beyond it. Raises ValueError with fewer than 20 returns or a
+    /// confidence outside (0, 1).
+    #[pyo3(signature = (confidence=0.95))]
+    fn var_cvar(&self, confidence: f64) -> PyResult<(f64, f64)> {
+        if !(confidence > 0.0 && confidence < 1.0) {
+            return Err(PyValueError::new_err(format!("confidence must be in (0, 1), got {confidence}")));
+        }
+        let mut rets: Vec<f64> = self.log_returns().into_iter().map(|(_, r)| r).collect();
+        if rets.len() < 20 {
+            return Err(PyValueError::new_err(format!("need at least 20 returns for VaR, have {}", rets.len())));
+        }
+        rets.sort_by(f64::total_cmp);
+        let idx = ((1.0 - confidence) * rets.len() as f64).floor() as usize;
+        let tail = &rets[..=idx.min(rets.len() - 1)];
+        let var = -tail[tail.len() - 1] * 100.0;
+        let cvar = -tail.iter().sum::<f64>() / tail.len() as f64 * 100.0;
+        Ok((var, cvar))
+    }
+
+    /// Time-weighted return over the run, as a percent: per-period returns linked
+    /// geometrically from starting equity through every mark, each against the previous mark
+    /// plus the capital deposited or withdrawn since, so flows don't move it. Without flows
+    /// this is last mark / starting equity - 1. 0.0 with no marks.
+    fn twr_pct(&self) -> f64 {
+        let mut prev = self.starting_equity;
+        let mut growth = 1.0;
+        for ((_, e), flow) in self.equity_curve.iter().zip(&self.mark_flows) {
+            if prev + flow != 0 { growth *= *e as f64 / (prev + flow) as f64; }
+            prev = *e;
+        }
+        (growth - 1.0) * 100.0
+    }
+
+    /// Money-weighted return: the annual IRR of the run's external cash flows, as a percent,
+    /// with time in `metric_periods_per_year` units and the starting point one period before the
+    /// first mark. The flows are starting equity in, each deposit in (and withdrawal out) at
+    /// the start of the period it was made in, and the last mark out; capital moved after the
+    /// last mark is ignored. With no deposits or withdrawals this is
+    /// (last / starting)^(periods_per_year / marks) - 1. 0.0 with no marks or when there is no
+    /// IRR (e.g. the last mark isn't positive).
+    fn mwr_pct(&self) -> f64 {
+        let Some((_, end)) = self.equity_curve.last() else { return 0.0 };
+        let mut flows = vec![(0.0, -(self.starting_equity as f64))];
+        for (i, flow) in self.mark_flows.iter().enumerate().filter(|(_, f)| **f != 0) {
+            flows.push((i as f64 / self.metric_periods_per_year(), -(*flow as f64)));
+        }
+        flows.push((self.equity_curve.len() as f64 / self.metric_periods_per_year(), *end as f64));
+        irr(&flows).map_or(0.0, |r| r * 100.0)
+    }
+
+    /// Return per `YYYY-MM`: last equity of the month / last equity of the month before - 1.
+    fn monthly_returns(&self) -> Vec<(String, f64)> {
+        self.period_returns(7)
+    }
+
+    /// Return per `YYYY`: last equity of the year / last equity of the year before - 1.
+    fn yearly_returns(&self) -> Vec<(String, f64)> {
+        self.period_returns(4)
+    }
+
+    /// Equity curve keeping the last mark of each calendar week ("W", Monday to Sunday) or
+    /// month ("M"), dated as that mark. For output only; metrics use every mark. Raises
+    /// ValueError for another `freq` or a mark date that doesn't start with YYYY-MM-DD.
+    fn resample_equity(&self, freq: &str) -> PyResult<Vec<(String, Money)>> {
+        let mut out: Vec<(String, Money)> = Vec::new();
+        let mut last_key: Option<String> = None;
+        for (date, equity) in &self.equity_curve {
+            let key = match freq {
+                // 1970-01-01 was a Thursday, so this numbers Monday-to-Sunday weeks
+                "W" => day_number(date).map(|d| (d + 3).div_euclid(7).to_string()),
+                "M" => day_number(date).map(|_| date[..7].to_string()),
+                _ => return Err(PyValueError::new_err(format!("freq must be W or M, got {freq}"))),
+            };
+            let Some(key) = key else {
+                return Err(PyValueError::new_err(format!("cannot bucket equity mark dated {date}")));
+            };
+            if last_key.as_ref() == Some(&key) { out.pop(); }
+            out.push((date.clone(), *equity));
+            last_key = Some(key);
+        }
+        Ok(out)
+    }
+
+    fn fills(&self) -> Vec<FillRow> {
+        self.fills.iter().map(Fill::row).collect()
+    }
+
+    /// Fills in `symbol`, in fill order, as `fills()` rows.
+    fn fills_for_symbol(&self, symbol: String) -> Vec<FillRow> {
+        self.fills.iter().filter(|f| f.symbol == symbol).map(Fill::row).collect()
+    }
+
+    /// Fills dated from `start_date` through `end_date`, both inclusive, as `fills()` rows.
+    /// Dates compare as strings, so a bare date as `end_date` excludes timestamps later that day.
+    fn fills_in_range(&self, start_date: String, end_date: String) -> Vec<FillRow> {
+        self.fills.iter().filter(|f| f.date >= start_date && f.date <= end_date).map(Fill::row).collect()
+    }
+
+    /// Completed round trips, one per reducing fill: (symbol, entry_date, exit_date, qty,
+    /// entry_avg_price, exit_price, pnl_paise, holding_days, mae_paise, mfe_paise). qty is
+    /// negative for shorts and holding_days counts calendar days from the date the position was
+    /// opened. MAE/MFE are the maximum adverse and favourable excursions: how far the position's
+    /// closes (and its fill prices) moved against and in favour of the entry price while it was
+    /// open, in paise on the closed qty and never negative.
+    fn closed_trades(&self) -> Vec<ClosedTradeRow> {
+        self.closed_trades.iter().map(|t| (
+            t.symbol.clone(), t.entry_date.clone(), t.exit_date.clone(), qty_to_f64(t.qty),
+            t.entry_price, t.exit_price, t.pnl, days_between(&t.entry_date, &t.exit_date),
+            t.mae, t.mfe,
+        )).collect()
+    }
+
+    /// Realized PnL of the closed trades in each symbol, which sums to `realized_pnl_paise`:
+    /// (symbol, pnl) sorted by symbol, for every symbol with a closed trade.
+    fn realized_pnl_by_symbol(&self) -> Vec<(String, Money)> {
+        let mut by_symbol: BTreeMap<String, Money> = BTreeMap::new();
+        for t in &self.closed_trades {
+            *by_symbol.entry(t.symbol.clone()).or_default() += t.pnl;
+        }
+        by_symbol.into_iter().collect()
+    }
+
+    /// Closed trades (as in `closed_trades`) counted by holding_days: (lower edge, count) for
+    /// each bucket from one edge up to, but not including, the next, the last one open-ended.
+    /// `edges` defaults to 0, 1, 2, 5, 10, 20, 60, 120 and 250 days, so 0 counts same-day round
+    /// trips; trades shorter than the first edge aren't counted. Empty buckets are listed with
+    /// a count of 0. Raises ValueError unless `edges` is non-empty and strictly increasing.
+    #[pyo3(signature = (edges=None))]
+    fn holding_period_histogram(&self, edges: Option<Vec<i64>>) -> PyResult<Vec<(i64, i64)>> {
+        let edges = edges.unwrap_or_else(|| vec![0, 1, 2, 5, 10, 20, 60, 120, 250]);
+        if edges.is_empty() || edges.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(PyValueError::new_err("edges must be non-empty and strictly increasing"));
+        }
+        let mut out: Vec<(i64, i64)> = edges.iter().map(|&e| (e, 0)).collect();
+        for t in &self.closed_trades {
+            let days = days_between(&t.entry_date, &t.exit_date);
+            if let Some(i) = edges.iter().rposition(|&e| e <= days) {
+                out[i].1 += 1;
+            }
+        }
+        Ok(out)
+    }
+
+    /// Open risk: what the open positions would lose against their avg cost if every stop
+    /// protecting them filled at its level at once. Pending stop and trailing stop orders on
+    /// the closing side (SELL for a long, BUY for a short) cover a position's qty in order id
+    /// order, each covered share risking entry - stop for a long and stop - entry for a short,
+    /// floored at 0 for stops that lock in a gain. Qty no stop covers risks `default_risk_pct`
+    /// of its avg-cost value, or nothing without it. Slippage and gaps through the stop are
+    /// left out, and a trailing stop that hasn't seen a price yet covers nothing. Raises
+    /// ValueError if a stop needs an FX rate that isn't set.
+    fn open_risk_paise(&self) -> PyResult<Money> {
+        let mut stops: Vec<&PendingOrder> = self.pending_orders.iter()
+            .filter(|o| matches!(o.kind, OrderType::Stop(_) | OrderType::TrailingStop { .. }))
+            .collect();
+        stops.sort_by_key(|o| o.id);
+        let mut total: Money = 0;
+        for (sym, &q) in self.positions.iter() {
+            if q == 0 { continue; }
+            let entry = *self.avg_cost.get(sym).unwrap_or(&0);
+            let exit_side = if q > 0 { "SELL" } else { "BUY" };
+            let date = self.last_bar_by_symbol.get(sym).map(|b| b.date.clone()).unwrap_or_default();
+            let mut uncovered = q.abs();
+            for o in stops.iter().filter(|o| o.symbol == *sym && o.side == exit_side) {
+                if uncovered == 0 { break; }
+                let Some(level) = o.kind.level(&o.side) else { continue };
+                let level = self.to_base(sym, &date, level)?;
+                let covered = o.qty.min(uncovered);
+                let per_share = if q > 0 { entry - level } else { level - entry };
+                total += value_of(per_share.max(0), covered);
+                uncovered -= covered;
+            }
+            if let Some(pct) = self.default_risk_pct {
+                total += (value_of(entry, uncovered) as f64 * pct / 100.0) as Money;
+            }
+        }
+        Ok(total)
+    }
+
+    /// MAE/MFE so far for positions still open, against their avg cost and up to the last
+    /// marked bar: (symbol, mae_paise, mfe_paise), sorted by symbol.
+    fn open_excursions(&self) -> Vec<(String, Money, Money)> {
+        let mut out: Vec<(String, Money, Money)> = self.positions.iter()
+            .filter(|(_, q)| **q != 0)
+            .map(|(sym, q)| {
+                let (mae, mfe) = self.excursion(sym, *q, *self.avg_cost.get(sym).unwrap_or(&0));
+                (sym.clone(), mae, mfe)
+            })
+            .collect();
+        out.sort();
+        out
+    }
+
+    /// Dividends applied so far: (date, symbol, cash delta).
+    fn dividends_received(&self) -> Vec<(String, String, Money)> {
+        self.dividends.clone()
+    }
+
+    /// Every cash movement: (date, category, delta) with category BUY, SELL, FEE, REBATE,
+    /// DIVIDEND, INTEREST, DEPOSIT or WITHDRAWAL. starting cash + sum of deltas == cash().
+    fn cash_flows(&self) -> Vec<(String, String, Money)> {
+        self.cash_flows.clone()
+    }
+
+    /// Fills as CSV with header `date,symbol,side,qty,price,fee,order_id`. price and fee are
+    /// integer paise; qty is an exact decimal share count.
+    fn fills_to_csv(&self) -> String {
+        let mut out = String::from("date,symbol,side,qty,price,fee,order_id\n");
+        for f in &self.fills {
+            out += &format!("{},{},{},{},{},{},{}\n",
+                csv_field(&f.date), csv_field(&f.symbol), csv_field(&f.side), format_qty(f.qty), f.price, f.fee, f.order_id);
+        }
+        out
+    }
+
+    /// Equity curve as CSV with header `date,equity`; equity is integer paise.
+    fn equity_curve_to_csv(&self) -> String {
+        let mut out = String::from("date,equity\n");
+        for (date, equity) in &self.equity_curve {
+            out += &format!("{},{}\n", csv_field(date), equity);
+        }
+        out
+    }
+
+    /// Orders still pending, in placement order: (order_id, symbol, side, qty, type, price).
+    /// type is MARKET, LIMIT, STOP or TRAILING_STOP; price is the limit or trigger (the trailing
+    /// stop's current level, None until the symbol has a price) and None for market orders.
+    fn open_orders(&self) -> Vec<OpenOrderRow> {
+        let mut rows: Vec<OpenOrderRow> = self.pending_orders.iter()
+            .map(|o| (o.id, o.symbol.clone(), o.side.clone(), qty_to_f64(o.qty), o.kind.name().to_string(), o.kind.level(&o.side)))
+            .collect();
+        rows.sort_by_key(|r| r.0);
+        rows
+    }
+
+    /// Shares filled so far and their average price for `order_id`, across every partial fill,
+    /// e.g. a limit order capped by `max_participation_pct` over several bars. None until the
+    /// order has a fill.
+    fn order_fill_summary(&self, order_id: i64) -> Option<(f64, f64)> {
+        let (qty, value) = self.fills.iter().filter(|f| f.order_id == order_id)
+            .fold((0, 0.0), |(q, v), f| (q + f.qty, v + f.price as f64 * qty_to_f64(f.qty)));
+        (qty > 0).then(|| (qty_to_f64(qty), value / qty_to_f64(qty)))
+    }
+
+    /// Orders dropped instead of filled: (order_id, symbol, reason).
+    fn rejected_orders(&self) -> Vec<(i64, String, String)> {
+        self.rejected_orders.clone()
+    }
+
+    /// Equity marks that breached `leverage_limit`: (date, gross exposure, equity).
+    fn margin_calls(&self) -> Vec<(String, Money, Money)> {
+        self.margin_calls.clone()
+    }
+
+    /// Fills cut short by a position limit, or to the long without `allow_short`: (order_id,
+    /// symbol, date, shares cut). The cut shares are dropped from the order.
+    fn truncated_orders(&self) -> Vec<(i64, String, String, f64)> {
+        self.truncated_orders.clone()
+    }
+
+    /// Orders rounded down to whole lots: (order_id, symbol, date, shares cut); see
+    /// `set_lot_size`.
+    fn lot_adjustments(&self) -> Vec<(i64, String, String, f64)> {
+        self.lot_adjustments.clone()
+    }
+
+    /// DAY orders cancelled unfilled at the end of their first bar: (order_id, symbol, date).
+    /// A partially filled DAY order expires its remainder.
+    fn expired_orders(&self) -> Vec<(i64, String, String)> {
+        self.expired_orders.clone()
+    }
+
+    /// Annualized sharpe over each trailing `window` of per-period returns, dated at the
+    /// window's last mark. Empty when the window is 0 or longer than the return history; a
+    /// window of 1 has no variance so every value is 0.0.
+    fn rolling_sharpe(&self, window: usize) -> Vec<(String, f64)> {
+        let rets = self.log_returns();
+        if window == 0 || window > rets.len() { return Vec::new(); }
+        let values: Vec<f64> = rets.iter().map(|(_, r)| *r).collect();
+        (window..=rets.len()).map(|end| (rets[end - 1].0.clone(), self.sharpe_of(&values[end - window..end]))).collect()
     }
 
     fn metrics(&self) -> Metrics {
+        if let Some(date) = &self.warmup_until {
+            return Python::with_gil(|py| self.after_warmup(py, date).metrics());
+        }
         let win_rate = if self.trades_closed > 0 {
             (self.wins as f64) / (self.trades_closed as f64)
         } else { 0.0 };
+        let profit_factor = if self.gross_loss > 0 {
+            self.gross_profit as f64 / self.gross_loss as f64
+        } else { 0.0 };
+        let expectancy_paise = if self.trades_closed > 0 {
+            self.realized_pnl as f64 / self.trades_closed as f64
+        } else { 0.0 };
 
         // Compute daily arithmetic returns, sharpe, annual return and annual volatility.
         let mut sharpe_val: f64 = 0.0;
+        let mut sortino_val: f64 = 0.0;
+        let mut omega: f64 = 0.0;
         let mut annual_return_pct: f64 = 0.0;
         let mut volatility_pct: f64 = 0.0;
         if self.equity_curve.len() >= 2 {
-        let mut rets: Vec<f64> = Vec::new();
-        for i in 1..self.equity_curve.len() {
-            let prev = self.equity_curve[i - 1].1 as f64;
-            let cur = self.equity_curve[i].1 as f64;
-            if prev == 0.0 { continue; }
-            // log return
-            rets.push((cur / prev).ln());
-        }
+        let rets: Vec<f64> = self.log_returns().into_iter().map(|(_, r)| r).collect();
         if !rets.is_empty() {
             let n = rets.len() as f64;
             let mean = rets.iter().sum::<f64>() / n;
@@ -266,17 +2705,23 @@ impl Engine {
             } else {
                 0.0
             };
-            // risk-free annual rate (default 0.0). Change here if you want a different Rf.
-            let rf_annual = 0.0_f64;
-            let rf_daily = rf_annual / 252.0;
-            if sd != 0.0 {
-                let mean_excess = mean - rf_daily;
-                sharpe_val = (mean_excess / sd) * (252f64).sqrt();
+            let rf = self.rf_per_period();
+            sharpe_val = self.sharpe_of(&rets);
+            // Sortino: same as sharpe but over downside deviation, i.e. the RMS of the
+            // shortfall below the minimum acceptable return (MAR = Rf).
+            let downside = rets.iter().map(|r| (r - rf).min(0.0).powi(2)).sum::<f64>() / n;
+            let downside_dev = downside.sqrt();
+            if downside_dev != 0.0 {
+                sortino_val = ((mean - rf) / downside_dev) * self.metric_periods_per_year().sqrt();
             }
+            let threshold = (1.0 + self.omega_threshold_pct / 100.0).ln();
+            let gains: f64 = rets.iter().map(|r| (r - threshold).max(0.0)).sum();
+            let losses: f64 = rets.iter().map(|r| (threshold - r).max(0.0)).sum();
+            if losses > 0.0 { omega = gains / losses; }
             // annualized return from log-returns, convert to percent
-            annual_return_pct = (mean * 252.0).exp() - 1.0;
-            annual_return_pct = annual_return_pct * 100.0;
-            volatility_pct = sd * (252f64).sqrt() * 100.0;
+            annual_return_pct = (mean * self.metric_periods_per_year()).exp() - 1.0;
+            annual_return_pct *= 100.0;
+            volatility_pct = sd * self.metric_periods_per_year().sqrt() * 100.0;
         }
         }
 
@@ -286,24 +2731,995 @@ impl Engine {
         } else {
             0.0
         };
+        // A drawdown still open at the end runs to the last mark
+        let ongoing_dd_days = match self.equity_curve.last() {
+            Some((date, e)) if *e < self.peak_equity => days_between(&self.peak_date, date),
+            _ => 0,
+        };
+        let calmar = if dd_pct > 0.0 { annual_return_pct / dd_pct } else { 0.0 };
+        let recovery_factor = match self.equity_curve.last() {
+            Some((_, end)) if self.max_dd > 0 => {
+                (end - self.starting_equity - self.mark_flows.iter().sum::<Money>()) as f64 / self.max_dd as f64
+            }
+            _ => 0.0,
+        };
+
+        let dd_curve = self.drawdown_curve();
+        let ulcer_index = if dd_curve.is_empty() { 0.0 } else {
+            (dd_curve.iter().map(|(_, dd)| dd * dd).sum::<f64>() / dd_curve.len() as f64).sqrt()
+        };
+        let pain_ratio = if ulcer_index > 0.0 { annual_return_pct / ulcer_index } else { 0.0 };
+
+        // Turnover: traded notional per unit of average equity, scaled from the marks seen to a year
+        let marks = self.equity_curve.len() as f64;
+        let traded: f64 = self.fills.iter().map(|f| value_of(f.price, f.qty).abs() as f64).sum();
+        let (avg_gross_exposure, turnover_annual) = if marks > 0.0 {
+            let avg_equity = self.equity_curve.iter().map(|(_, e)| *e as f64).sum::<f64>() / marks;
+            let turnover = if avg_equity > 0.0 { traded / avg_equity * self.metric_periods_per_year() / marks } else { 0.0 };
+            (self.exposure_sum / marks, turnover)
+        } else { (0.0, 0.0) };
+        let (beta, alpha_annual_pct, information_ratio) = self.benchmark_stats();
+        let fees_pct_of_pnl = if self.realized_pnl != 0 { self.fees_paid as f64 / self.realized_pnl.abs() as f64 * 100.0 } else { 0.0 };
+        let fees_pct_of_notional = if traded > 0.0 { self.fees_paid as f64 / traded * 100.0 } else { 0.0 };
+
+        // Compounded growth between the first and last marks, net of deposits and withdrawals.
+        // Matches annual_return_pct, which compounds the mean log return, unless a zero-equity
+        // mark was skipped there.
+        let cagr_pct = match (self.equity_curve.first(), self.equity_curve.last()) {
+            (Some((_, start)), Some((_, end))) if self.equity_curve.len() >= 2 && *start > 0 && *end > 0 => {
+                let periods = (self.equity_curve.len() - 1) as f64;
+                let growth = self.log_returns().iter().map(|(_, r)| r).sum::<f64>().exp();
+                (growth.powf(self.metric_periods_per_year() / periods) - 1.0) * 100.0
+            }
+            _ => 0.0,
+        };
 
-        Metrics {
+        // The closed-trade log carries the side in the sign of qty
+        let (mut long_pnl, mut short_pnl) = (0, 0);
+        let (mut closed_slippage, mut closed_fees) = (0, 0);
+        let (mut long_trades, mut short_trades, mut long_wins, mut short_wins) = (0, 0, 0, 0);
+        let (mut win_streak, mut loss_streak, mut max_wins, mut max_losses) = (0, 0, 0, 0);
+        for t in &self.closed_trades {
+            closed_slippage += t.slippage;
+            closed_fees += t.fees;
+            if t.pnl > 0 {
+                win_streak += 1;
+                loss_streak = 0;
+            } else {
+                loss_streak += 1;
+                win_streak = 0;
+            }
+            max_wins = max_wins.max(win_streak);
+            max_losses = max_losses.max(loss_streak);
+            if t.qty > 0 {
+                long_pnl += t.pnl;
+                long_trades += 1;
+                if t.pnl > 0 { long_wins += 1; }
+            } else {
+                short_pnl += t.pnl;
+                short_trades += 1;
+                if t.pnl > 0 { short_wins += 1; }
+            }
+        }
+
+        let mut m = Metrics {
             realized_pnl_paise: self.realized_pnl,
             fees_paise: self.fees_paid,
+            rebates_paise: self.rebates,
+            slippage_paise: self.slippage_paid,
+            gross_realized_pnl_paise: self.realized_pnl + closed_slippage,
+            net_realized_pnl_paise: self.realized_pnl - closed_fees,
+            fees_pct_of_pnl,
+            fees_pct_of_notional,
+            interest_paise: self.interest,
             trades_closed: self.trades_closed,
+            long_realized_pnl_paise: long_pnl,
+            short_realized_pnl_paise: short_pnl,
+            long_trades_closed: long_trades,
+            short_trades_closed: short_trades,
+            long_wins,
+            short_wins,
+            max_consecutive_wins: max_wins,
+            max_consecutive_losses: max_losses,
             win_rate,
+            profit_factor,
+            expectancy_paise,
             max_drawdown_paise: self.max_dd,
             max_drawdown_pct: dd_pct,
+            max_drawdown_days: self.max_dd_days.max(ongoing_dd_days),
             sharpe: sharpe_val,
+            sortino: sortino_val,
+            omega,
             annual_return_pct,
+            cagr_pct,
             volatility: volatility_pct,
+            calmar,
+            recovery_factor,
+            avg_gross_exposure,
+            turnover_annual,
+            ulcer_index,
+            pain_ratio,
+            beta,
+            alpha_annual_pct,
+            information_ratio,
+            max_relative_drawdown_pct: self.relative_drawdown_curve().iter().map(|(_, dd)| *dd).fold(0.0, f64::max),
+            blown_up_date: self.blown_up_date.clone(),
+            is_valid: self.log_returns().len() >= 2,
+        };
+        if m.blown_up_date.is_some() {
+            // Returns through non-positive equity are meaningless; report the loss, not ratios
+            m.annual_return_pct = -100.0;
+            m.cagr_pct = -100.0;
+            m.sharpe = 0.0;
+            m.sortino = 0.0;
+            m.omega = 0.0;
+            m.calmar = 0.0;
+            m.pain_ratio = 0.0;
+            m.alpha_annual_pct = 0.0;
+            m.information_ratio = 0.0;
+        }
+        m
+    }
+}
+
+impl Engine {
+    /// Append a state-changing call to `call_log` when `record_calls` is on, snapshotting the
+    /// engine to replay from before the first one. Returns the entry's index for `logged`.
+    fn record(&mut self, method: &str, args: impl ToPyObject) -> PyResult<Option<usize>> {
+        if !self.record_calls { return Ok(None); }
+        Python::with_gil(|py| {
+            if self.call_log_start.is_none() {
+                self.call_log_start = Some(self.to_json(py)?);
+            }
+            // () converts to None rather than an empty tuple
+            let args = args.to_object(py);
+            let args = if args.is_none(py) { PyTuple::empty_bound(py).into() } else { args };
+            self.call_log.push((method.to_string(), args, None));
+            Ok(Some(self.call_log.len() - 1))
+        })
+    }
+
+    /// Finish the call `record` logged as `entry`, noting the error it raised, if any, so
+    /// `replay` can tell a call that failed when recorded from one that fails only on replay.
+    fn logged<T>(&mut self, entry: Option<usize>, result: PyResult<T>) -> PyResult<T> {
+        if let (Some(i), Err(e)) = (entry, &result) {
+            self.call_log[i].2 = Some(e.to_string());
+        }
+        result
+    }
+
+    /// Drawdown tracking for a new mark. Starting cash counts as a peak dated at the first mark;
+    /// a drawdown lasts until equity gets back to its peak.
+    fn track_drawdown(&mut self, date: &str, equity: Money, flow: Money, was_underwater: bool) {
+        // Capital added or taken out moves the peak with it rather than counting as a return
+        self.peak_equity += flow;
+        if self.peak_date.is_empty() { self.peak_date = date.to_string(); }
+        if equity >= self.peak_equity {
+            if was_underwater {
+                self.max_dd_days = self.max_dd_days.max(days_between(&self.peak_date, date));
+            }
+            self.peak_equity = equity;
+            self.peak_date = date.to_string();
+        }
+        let dd = self.peak_equity - equity;
+        if dd > self.max_dd { self.max_dd = dd; }
+    }
+
+    /// What `metrics` sees under `set_warmup_until(date)`: a config copy holding only the marks
+    /// from the first one on or after `date`, that mark as starting equity, and the closed
+    /// trades, fills and interest dated from `date` on, with the running totals rebuilt from them.
+    fn after_warmup(&self, py: Python<'_>, date: &str) -> Engine {
+        let mut v = self.clone_config(py);
+        v.warmup_until = None;
+        let k = self.equity_curve.iter().position(|(d, _)| d.as_str() >= date).unwrap_or(self.equity_curve.len());
+        v.equity_curve = self.equity_curve[k..].to_vec();
+        v.exposure_curve = self.exposure_curve[k..].to_vec();
+        v.mark_flows = self.mark_flows[k..].to_vec();
+        // Capital moved up to the first mark is already in it
+        if let Some(flow) = v.mark_flows.first_mut() { *flow = 0; }
+        if let Some((_, e)) = v.equity_curve.first() {
+            v.starting_equity = *e;
+            v.peak_equity = *e;
+        }
+        for i in 0..v.equity_curve.len() {
+            let (d, e) = v.equity_curve[i].clone();
+            let was_underwater = i > 0 && v.equity_curve[i - 1].1 < v.peak_equity;
+            v.track_drawdown(&d, e, v.mark_flows[i], was_underwater);
+        }
+        v.exposure_sum = v.equity_curve.iter().zip(&v.exposure_curve)
+            .filter(|((_, e), _)| *e > 0)
+            .map(|((_, e), (_, _, gross))| *gross as f64 / *e as f64)
+            .sum();
+
+        v.closed_trades = self.closed_trades.iter().filter(|t| t.exit_date.as_str() >= date).cloned().collect();
+        for t in &v.closed_trades {
+            v.realized_pnl += t.pnl;
+            v.trades_closed += 1;
+            if t.pnl > 0 {
+                v.wins += 1;
+                v.gross_profit += t.pnl;
+            } else {
+                v.gross_loss -= t.pnl;
+            }
+        }
+        v.fills = self.fills.iter().filter(|f| f.date.as_str() >= date).cloned().collect();
+        for f in &v.fills {
+            if f.fee < 0 { v.rebates -= f.fee; } else { v.fees_paid += f.fee; }
+            v.slippage_paid += f.slippage;
+        }
+        v.interest = self.cash_flows.iter().filter(|(d, c, _)| d.as_str() >= date && c == "INTEREST").map(|(_, _, x)| x).sum();
+        v.blown_up_date = self.blown_up_date.clone();
+        v
+    }
+
+    /// Repeat the last equity mark (and its exposure) for `date`, for `fill_missing_days`.
+    fn carry_mark(&mut self, date: &str) {
+        let Some((_, equity)) = self.equity_curve.last().cloned() else { return };
+        let (_, net, gross) = self.exposure_curve.last().cloned().unwrap_or_default();
+        if equity > 0 { self.exposure_sum += gross as f64 / equity as f64; }
+        self.equity_curve.push((date.to_string(), equity));
+        self.exposure_curve.push((date.to_string(), net, gross));
+        self.mark_flows.push(0);
+    }
+
+    /// `liquidate_all` without logging the call, for when the engine flattens the book itself.
+    fn flatten_positions(&mut self) -> Vec<i64> {
+        let mut open: Vec<(String, Qty)> = self.positions.iter().filter(|(_, q)| **q != 0).map(|(s, q)| (s.clone(), *q)).collect();
+        open.sort();
+        open.into_iter().map(|(sym, q)| {
+            let side = if q > 0 { "SELL" } else { "BUY" };
+            // Exempt from the daily loss limit: flattening is what it's there for
+            let order = self.new_order(sym, side.to_string(), q.abs(), OrderType::Market, "GTC");
+            let id = order.id;
+            self.pending_orders.push(order);
+            id
+        }).collect()
+    }
+
+    /// The errors `fill_pending_orders` can raise for `date`, checked without changing anything:
+    /// in strict mode a pending order without a bar, and a missing FX rate for one with a bar.
+    /// Nothing once `halt_on_blowup` has stopped fills.
+    fn check_fillable(&self, date: &str) -> PyResult<()> {
+        if self.halt_on_blowup && self.blown_up_date.is_some() { return Ok(()); }
+        if self.strict && let Some(o) = self.pending_orders.iter().find(|o| !self.has_bar(&o.symbol, date)) {
+            return Err(PyValueError::new_err(format!("order {} for {} has no bar on {date}", o.id, o.symbol)));
+        }
+        for o in self.pending_orders.iter().filter(|o| self.has_bar(&o.symbol, date)) {
+            self.fx_rate(&o.symbol, date)?;
+        }
+        Ok(())
+    }
+
+    /// Try to fill every pending order against its symbol's bar for `date`, in `fill_priority`
+    /// order. Exits attached by a bracket fill wait for the next pass.
+    fn fill_pending_orders(&mut self, date: &str) -> PyResult<()> {
+        if self.halt_on_blowup && self.blown_up_date.is_some() { return Ok(()); }
+        // Fail before taking the queue apart rather than mid-pass
+        self.check_fillable(date)?;
+
+        let mut still_pending = Vec::new();
+        let mut cancelled = HashSet::new(); // OCO siblings of orders filled in this pass
+
+        let mut queue = std::mem::take(&mut self.pending_orders);
+        match self.fill_priority {
+            FillPriority::FifoByOrderId => queue.sort_by_key(|o| o.id),
+            FillPriority::SellsFirst => queue.sort_by_key(|o| (o.side != "SELL", o.id)),
+        }
+
+        let mut queue = queue.into_iter();
+        while let Some(mut order) = queue.next() {
+            if cancelled.contains(&order.id) { continue; }
+            let bar = match self.last_bar_by_symbol.get(&order.symbol) {
+                Some(b) if b.date == date => b.clone(),
+                _ => { still_pending.push(order); continue; }
+            };
+            // At-the-close orders only see the closing print, not the bar's range
+            let timing = match order.timing.as_deref() {
+                Some("MOO") => FillTiming::NextOpen,
+                Some("MOC") => FillTiming::NextClose,
+                _ => self.fill_timing,
+            };
+            let bar = match timing {
+                FillTiming::NextOpen => bar,
+                FillTiming::SameClose | FillTiming::NextClose => Bar { open: bar.close, high: bar.close, low: bar.close, ..bar },
+            };
+
+            let lot = self.lot_sizes.get(&order.symbol).copied().unwrap_or(1);
+            if order.qty < lot {
+                self.rejected_orders.push((order.id, order.symbol, "BELOW_LOT_SIZE".to_string()));
+                continue;
+            }
+            if order.qty % lot != 0 {
+                let cut = order.qty % lot;
+                self.lot_adjustments.push((order.id, order.symbol.clone(), date.to_string(), qty_to_f64(cut)));
+                order.qty -= cut;
+            }
+
+            // Partial fill when the order is bigger than the bar's participation cap
+            let mut qty = match self.max_participation_pct {
+                Some(pct) => order.qty.min(shares((bar.volume as f64 * pct / 100.0) as i64)),
+                None => order.qty,
+            };
+            qty -= qty % lot;
+            let slippage_bps = self.slippage_bps_for(bar.volume, qty);
+            let slippage_bps = match (order.kind, self.prev_close.get(&order.symbol)) {
+                (OrderType::Market, Some(&prev)) if timing == FillTiming::NextOpen && prev > 0 => {
+                    let gap_bps = (bar.open - prev).abs() as f64 * 10_000.0 / prev as f64;
+                    let extra = (self.gap_slippage_coeff * (gap_bps - self.gap_threshold_bps as f64).max(0.0)).round() as i64;
+                    slippage_bps.map(|b| b + extra)
+                }
+                _ => slippage_bps,
+            };
+
+            order.kind.ratchet(&order.side, bar.open);
+            let px = match fill_price(&bar, &order, slippage_bps.unwrap_or(self.slippage_model.bps()), self.slippage_model.per_share()) {
+                Some(p) => p,
+                None => {
+                    let best = if order.side == "SELL" { bar.high } else { bar.low };
+                    order.kind.ratchet(&order.side, best);
+                    self.carry_over(order, date, &mut still_pending);
+                    continue;
+                }
+            };
+
+            if slippage_bps.is_none() {
+                self.rejected_orders.push((order.id, order.symbol, "ZERO_VOLUME".to_string()));
+                continue;
+            }
+            // The same fill without slippage, to cost it
+            let raw_px = fill_price(&bar, &order, 0, 0).unwrap_or(px);
+            let px = self.tick_round(&order, px);
+            // Everything from here on (fees, limits, cash, the fill record) is in base currency
+            let px = self.to_base(&order.symbol, date, px)?;
+            let raw_px = self.to_base(&order.symbol, date, raw_px)?;
+            if qty == 0 && order.qty > 0 { self.carry_over(order, date, &mut still_pending); continue; }
+
+            let allowed = self.position_limit_qty(&order.symbol, &order.side, px)?;
+            if qty > allowed {
+                if self.reject_over_position_limit {
+                    self.rejected_orders.push((order.id, order.symbol, "POSITION_LIMIT".to_string()));
+                    continue;
+                }
+                // Truncated shares are dropped, not carried over
+                let cut = qty - allowed;
+                self.truncated_orders.push((order.id, order.symbol.clone(), date.to_string(), qty_to_f64(cut)));
+                if allowed == 0 { continue; }
+                order.qty -= cut;
+                qty = allowed;
+            }
+            let long = self.positions.get(&order.symbol).copied().unwrap_or(0).max(0);
+            if order.side == "SELL" && !self.allow_short && qty > long {
+                if long == 0 {
+                    self.rejected_orders.push((order.id, order.symbol, "SHORT_NOT_ALLOWED".to_string()));
+                    continue;
+                }
+                // Like a position limit: the excess is dropped, not carried over
+                let cut = order.qty - long;
+                self.truncated_orders.push((order.id, order.symbol.clone(), date.to_string(), qty_to_f64(cut)));
+                order.qty = long;
+                qty = long;
+            }
+
+            let notional = value_of(px, qty.abs());
+            let fee = match self.fill_fee(&order, qty, px, notional) {
+                Ok(fee) => fee,
+                Err(e) => {
+                    // A raising fee callback: fills so far stand, this order and the rest wait
+                    still_pending.push(order);
+                    still_pending.extend(queue);
+                    still_pending.retain(|o| !cancelled.contains(&o.id));
+                    self.pending_orders = still_pending;
+                    return Err(e);
+                }
+            };
+
+            if let Some(limit) = self.leverage_limit {
+                let delta = if order.side == "BUY" { qty } else { -qty };
+                let (before, after, equity) = self.exposure_around_fill(&order.symbol, delta, px)?;
+                if after > before && after as f64 > limit * (equity - fee) as f64 {
+                    self.rejected_orders.push((order.id, order.symbol, "LEVERAGE_LIMIT".to_string()));
+                    continue;
+                }
+            } else if order.side == "BUY" && self.reject_on_insufficient_cash && notional + fee > self.cash - self.unsettled_cash() {
+                self.rejected_orders.push((order.id, order.symbol, "INSUFFICIENT_CASH".to_string()));
+                continue;
+            }
+            if qty < order.qty {
+                let fees = Some(order.fees.unwrap_or(0) + fee);
+                self.carry_over(PendingOrder { qty: order.qty - qty, fees, ..order.clone() }, date, &mut still_pending);
+            }
+            let PendingOrder { id: oid, symbol: sym, side, kind, bracket, oco_with, leg, .. } = order;
+
+            // Update cash & position. A SELL beyond the current long opens a short,
+            // a BUY beyond the current short opens a long.
+            let fee_category = if fee < 0 { "REBATE" } else { "FEE" };
+            let slippage = value_of(if side == "BUY" { px - raw_px } else { raw_px - px }, qty);
+            if side == "BUY" {
+                self.post_cash(date, "BUY", -notional);
+                self.post_cash(date, fee_category, -fee);
+                self.apply_to_position(&sym, qty, px, date, slippage, fee);
+            } else if side == "SELL" {
+                self.post_cash(date, "SELL", notional);
+                if self.settlement_days > 0 { self.unsettled.push((self.settlement_days, notional)); }
+                self.post_cash(date, fee_category, -fee);
+                self.apply_to_position(&sym, -qty, px, date, slippage, fee);
+            }
+
+            if fee < 0 { self.rebates -= fee; } else { self.fees_paid += fee; }
+            self.slippage_paid += slippage;
+
+            self.fills.push(Fill {
+                date: date.to_string(),
+                symbol: sym.clone(),
+                side: side.clone(),
+                qty,
+                price: px,
+                fee,
+                order_id: oid,
+                limit: match kind { OrderType::Limit(l) => Some(l), _ => None },
+                leg,
+                slippage,
+            });
+
+            if let Some(sibling) = oco_with {
+                cancelled.insert(sibling);
+            }
+            if let Some(b) = bracket {
+                let exit_side = if side == "BUY" { "SELL" } else { "BUY" };
+                let mut stop = self.new_order(sym.clone(), exit_side.to_string(), qty, OrderType::Stop(b.stop), "GTC");
+                let mut target = self.new_order(sym, exit_side.to_string(), qty, OrderType::Limit(b.target), "GTC");
+                stop.oco_with = Some(target.id);
+                stop.leg = Some("STOP".to_string());
+                target.oco_with = Some(stop.id);
+                target.leg = Some("TARGET".to_string());
+                still_pending.push(stop);
+                still_pending.push(target);
+            }
+        }
+
+        still_pending.retain(|o| !cancelled.contains(&o.id));
+        self.pending_orders = still_pending;
+        Ok(())
+    }
+
+    /// Group the equity curve by the first `key_len` chars of the date and return
+    /// end / previous group's end - 1 per group, in curve order, linking the per-mark returns
+    /// so deposits and withdrawals don't count and the groups compound to the whole curve's
+    /// return. The first group starts from its first mark, so a single-point first group
+    /// returns 0.0.
+    fn period_returns(&self, key_len: usize) -> Vec<(String, f64)> {
+        let mut out: Vec<(String, f64)> = Vec::new();
+        let mut growth = 1.0;
+        for (i, (date, equity)) in self.equity_curve.iter().enumerate() {
+            let key = date.get(..key_len).unwrap_or(date);
+            if out.last().is_none_or(|(k, _)| k != key) {
+                growth = 1.0;
+                out.push((key.to_string(), 0.0));
+            }
+            if let Some(base) = self.mark_base(i) {
+                growth *= *equity as f64 / base as f64;
+                out.last_mut().unwrap().1 = growth - 1.0;
+            }
+        }
+        out
+    }
+
+    /// (beta, alpha_annual_pct, information_ratio) from per-period log returns of the equity curve
+    /// against the benchmark on the same dates, with alpha on returns in excess of Rf.
+    /// All 0.0 with fewer than two aligned returns.
+    fn benchmark_stats(&self) -> (f64, f64, f64) {
+        let mut pairs: Vec<(f64, f64)> = Vec::new();
+        for i in 1..self.equity_curve.len() {
+            let (prev_date, _) = &self.equity_curve[i - 1];
+            let (date, cur) = &self.equity_curve[i];
+            if let (Some(&b0), Some(&b1), Some(prev)) = (self.benchmark.get(prev_date), self.benchmark.get(date), self.mark_base(i))
+                && prev > 0 && *cur > 0 && b0 > 0 && b1 > 0
+            {
+                pairs.push(((*cur as f64 / prev as f64).ln(), (b1 as f64 / b0 as f64).ln()));
+            }
+        }
+        if pairs.len() < 2 { return (0.0, 0.0, 0.0); }
+
+        let n = pairs.len() as f64;
+        let mean_s = pairs.iter().map(|p| p.0).sum::<f64>() / n;
+        let mean_b = pairs.iter().map(|p| p.1).sum::<f64>() / n;
+        let cov = pairs.iter().map(|(s, b)| (s - mean_s) * (b - mean_b)).sum::<f64>() / (n - 1.0);
+        let var_b = pairs.iter().map(|(_, b)| (b - mean_b).powi(2)).sum::<f64>() / (n - 1.0);
+        let beta = if var_b != 0.0 { cov / var_b } else { 0.0 };
+        let rf = self.rf_per_period();
+        let alpha_annual_pct = ((mean_s - rf) - beta * (mean_b - rf)) * self.metric_periods_per_year() * 100.0;
+
+        let mean_active = mean_s - mean_b;
+        let var_active = pairs.iter().map(|(s, b)| (s - b - mean_active).powi(2)).sum::<f64>() / (n - 1.0);
+        let information_ratio = if var_active > 0.0 { mean_active / var_active.sqrt() * self.metric_periods_per_year().sqrt() } else { 0.0 };
+        (beta, alpha_annual_pct, information_ratio)
+    }
+
+    /// Log return between consecutive equity marks, dated at the later mark and net of
+    /// capital moved in between (see `mark_base`); marks with a zero base are skipped.
+    fn log_returns(&self) -> Vec<(String, f64)> {
+        (1..self.equity_curve.len())
+            .filter_map(|i| {
+                let (date, e) = &self.equity_curve[i];
+                self.mark_base(i).map(|base| (date.clone(), (*e as f64 / base as f64).ln()))
+            })
+            .collect()
+    }
+
+    /// What mark `i`'s equity is measured against: the previous mark plus deposits less
+    /// withdrawals since, None for the first mark or a zero base.
+    fn mark_base(&self, i: usize) -> Option<Money> {
+        if i == 0 { return None; }
+        let base = self.equity_curve[i - 1].1 + self.mark_flows.get(i).copied().unwrap_or(0);
+        (base != 0).then_some(base)
+    }
+
+    /// Annualized sharpe of per-period log returns in excess of Rf, 0.0 with no variation.
+    fn sharpe_of(&self, rets: &[f64]) -> f64 {
+        if rets.len() < 2 { return 0.0; }
+        let n = rets.len() as f64;
+        let mean = rets.iter().sum::<f64>() / n;
+        let sd = (rets.iter().map(|r| (r - mean) * (r - mean)).sum::<f64>() / (n - 1.0)).sqrt();
+        if sd == 0.0 { return 0.0; }
+        (mean - self.rf_per_period()) / sd * self.metric_periods_per_year().sqrt()
+    }
+
+    /// The only place cash changes after construction, so the ledger always reconciles.
+    /// Zero deltas are not recorded.
+    fn post_cash(&mut self, date: &str, category: &str, delta: Money) {
+        if delta == 0 { return; }
+        self.cash += delta;
+        self.cash_flows.push((date.to_string(), category.to_string(), delta));
+    }
+
+    fn rf_per_period(&self) -> f64 {
+        self.risk_free_annual_pct / 100.0 / self.metric_periods_per_year()
+    }
+
+    fn has_bar(&self, sym: &str, date: &str) -> bool {
+        self.last_bar_by_symbol.get(sym).is_some_and(|b| b.date == date)
+    }
+
+    /// Pass a snapshot to the bar callback, if one is set; see `set_bar_callback`.
+    fn run_bar_fn(&self, stage: &str, date: &str, equity: Money) -> PyResult<()> {
+        let Some(f) = &self.bar_fn else { return Ok(()) };
+        Python::with_gil(|py| {
+            let positions = PyDict::new_bound(py);
+            for (sym, q) in self.positions.iter().filter(|(_, q)| **q != 0) {
+                positions.set_item(sym, qty_to_f64(*q))?;
+            }
+            let snapshot = PyDict::new_bound(py);
+            snapshot.set_item("cash", self.cash)?;
+            snapshot.set_item("equity", equity)?;
+            snapshot.set_item("positions", positions)?;
+            f.call1(py, (stage, date, snapshot))?;
+            Ok(())
+        })
+    }
+
+    /// Fill price `px` of `order`, in its symbol's currency, rounded to the symbol's tick size
+    /// against the trade without going through a limit; `px` as is without a tick size.
+    fn tick_round(&self, order: &PendingOrder, px: Money) -> Money {
+        match (self.tick_sizes.get(&order.symbol), order.kind) {
+            (Some(&tick), OrderType::Limit(limit)) if order.side == "BUY" => round_to_tick(px, tick, "BUY").min(limit),
+            (Some(&tick), OrderType::Limit(limit)) => round_to_tick(px, tick, "SELL").max(limit),
+            (Some(&tick), _) => round_to_tick(px, tick, &order.side),
+            (None, _) => px,
+        }
+    }
+
+    /// Base plus volume-impact slippage for filling `qty` on a bar of `volume`. None when
+    /// impact is on and the bar has no volume to price it against.
+    fn slippage_bps_for(&self, volume: i64, qty: Qty) -> Option<i64> {
+        if self.impact_bps == 0.0 {
+            Some(self.slippage_model.bps())
+        } else if volume == 0 {
+            None
+        } else {
+            Some(self.slippage_model.bps() + (self.impact_bps * qty_to_f64(qty.abs()) / volume as f64).round() as i64)
+        }
+    }
+
+    /// Base currency per unit of `sym`'s currency on `date`, in bps; 10_000 for base symbols.
+    fn fx_rate(&self, sym: &str, date: &str) -> PyResult<i64> {
+        let Some(ccy) = self.symbol_currency.get(sym) else { return Ok(10_000) };
+        self.fx_rates.get(ccy)
+            .and_then(|rates| rates.range::<str, _>((std::ops::Bound::Unbounded, std::ops::Bound::Included(date))).next_back())
+            .map(|(_, rate)| *rate)
+            .ok_or_else(|| PyValueError::new_err(format!("no {ccy} FX rate for {sym} on or before {date}")))
+    }
+
+    /// `px` in `sym`'s currency converted to base on `date`, rounded to the nearest paisa.
+    fn to_base(&self, sym: &str, date: &str, px: Money) -> PyResult<Money> {
+        let rate = self.fx_rate(sym, date)?;
+        if rate == 10_000 { return Ok(px); }
+        Ok((px.saturating_mul(rate) + px.signum() * 5_000) / 10_000)
+    }
+
+    /// Gross exposure before and after a fill of `delta` (signed) in `sym` at `px`, and equity
+    /// before it, with `sym` marked at `px` and every other position at its last close.
+    fn exposure_around_fill(&self, sym: &str, delta: Qty, px: Money) -> PyResult<(Money, Money, Money)> {
+        let (mut others, mut equity) = (0, self.cash);
+        for (s, q) in self.positions.iter() {
+            if s == sym || *q == 0 { continue; }
+            let value = self.position_value(s.clone())?;
+            others += value.abs();
+            equity += value;
+        }
+        let pos = self.positions.get(sym).copied().unwrap_or(0);
+        Ok((others + value_of(px, pos).abs(), others + value_of(px, pos + delta).abs(), equity + value_of(px, pos)))
+    }
+
+    /// Largest qty a `side` fill at `px` may have before |position| in `sym` exceeds a limit.
+    /// Fills that reduce the position are never limited.
+    fn position_limit_qty(&self, sym: &str, side: &str, px: Money) -> PyResult<Qty> {
+        let mut cap = self.max_position_qty.get(sym).copied();
+        if let Some(pct) = self.max_notional_pct && px > 0 {
+            let max_value = self.current_equity()? as f64 * pct / 100.0;
+            let by_value = (max_value.max(0.0) * QTY_SCALE as f64 / px as f64) as Qty;
+            cap = Some(cap.map_or(by_value, |c| c.min(by_value)));
+        }
+        let Some(cap) = cap else { return Ok(Qty::MAX) };
+        let pos = *self.positions.get(sym).unwrap_or(&0);
+        let room = (if side == "BUY" { cap - pos } else { cap + pos }).max(0);
+        Ok(room - room % self.lot_sizes.get(sym).copied().unwrap_or(1))
+    }
+
+    /// Keep an order that had a bar for `date` but didn't (fully) fill, unless it's a DAY order.
+    fn carry_over(&mut self, order: PendingOrder, date: &str, still_pending: &mut Vec<PendingOrder>) {
+        if order.tif == "DAY" {
+            self.expired_orders.push((order.id, order.symbol, date.to_string()));
+        } else {
+            still_pending.push(order);
+        }
+    }
+
+    fn new_order(&mut self, symbol: String, side: String, qty: Qty, kind: OrderType, tif: &str) -> PendingOrder {
+        let id = self.next_order_id;
+        self.next_order_id += 1;
+        PendingOrder { id, symbol, side, qty, kind, bracket: None, oco_with: None, leg: None, tif: tif.to_string(), timing: None, fees: None }
+    }
+
+    fn queue_order(&mut self, symbol: String, side: String, qty: Qty, kind: OrderType, tif: &str) -> PyResult<i64> {
+        let order = self.new_order(symbol, side, qty, kind, tif);
+        self.admit(order)
+    }
+
+    /// Queue a newly placed order, or reject it if the daily loss limit has been hit.
+    fn admit(&mut self, order: PendingOrder) -> PyResult<i64> {
+        let id = order.id;
+        if self.drawdown_stop_date.is_some() {
+            self.rejected_orders.push((id, order.symbol, "DRAWDOWN_STOP".to_string()));
+            return Ok(id);
+        }
+        let hit = match self.daily_loss_limit {
+            Some(limit) => {
+                let baseline = if self.loss_day.is_empty() {
+                    self.equity_curve.last().map_or(self.starting_equity, |(_, e)| *e) + self.pending_flow
+                } else {
+                    self.loss_day_equity
+                };
+                baseline - self.current_equity()? > limit
+            }
+            None => false,
+        };
+        if hit {
+            self.rejected_orders.push((id, order.symbol, "DAILY_LOSS_LIMIT".to_string()));
+        } else {
+            self.pending_orders.push(order);
+        }
+        Ok(id)
+    }
+
+    /// Reset the daily loss baseline to current equity when `date` starts a later day than any
+    /// seen so far (its YYYY-MM-DD prefix, or the whole string if it has none), before that
+    /// day's bars change the closes. Does nothing without `daily_loss_limit_paise`.
+    fn start_loss_day(&mut self, date: &str) -> PyResult<()> {
+        if self.daily_loss_limit.is_none() { return Ok(()); }
+        let day = if day_number(date).is_some() { &date[..10] } else { date };
+        if day > self.loss_day.as_str() {
+            self.loss_day_equity = self.current_equity()?;
+            self.loss_day = day.to_string();
         }
+        Ok(())
+    }
+
+    /// Move the position in `sym` by `delta` shares (signed) at `px`. The part that reduces
+    /// the existing position realizes PnL against its cost basis (or, under FIFO/LIFO cost
+    /// basis, against the lots it consumes); the part that adds to it (or opens the other side
+    /// after crossing zero) adds to the basis and sets the new avg cost. Each reduction is
+    /// logged as a closed trade, one per consumed lot under FIFO/LIFO.
+    ///
+    /// The basis is kept as the exact notional paid rather than avg cost * qty, so a reduction
+    /// takes its pro-rata share of it and avg cost rounding never leaks into PnL: realized plus
+    /// unrealized PnL always equals the positions' cash flows plus their market value.
+    /// The fill's `slippage` and `fee` are split the same way, see `attribute_costs`.
+    fn apply_to_position(&mut self, sym: &str, delta: Qty, px: Money, date: &str, slippage: Money, fee: Money) {
+        let old_q = *self.positions.get(sym).unwrap_or(&0);
+        let old_avg = *self.avg_cost.get(sym).unwrap_or(&0);
+        let old_basis = *self.basis.get(sym).unwrap_or(&0);
+        let new_q = old_q + delta;
+        let first_close = self.closed_trades.len();
+        let by_lot = self.cost_basis != CostBasis::Average;
+        if old_q == 0 {
+            self.entry_dates.insert(sym.to_string(), date.to_string());
+            self.excursions.insert(sym.to_string(), (px, px));
+        } else {
+            Self::touch_excursion(&mut self.excursions, sym, px);
+        }
+
+        let new_avg = if old_q == 0 || (old_q > 0) == (delta > 0) {
+            // opening or adding: weighted avg of the absolute sizes
+            if by_lot {
+                self.lots.entry(sym.to_string()).or_default().push(Lot { qty: delta, price: px, date: date.to_string() });
+            }
+            let new_basis = old_basis + value_of(px, delta.abs());
+            self.basis.insert(sym.to_string(), new_basis);
+            if new_q != 0 { self.rounding.div(new_basis.saturating_mul(QTY_SCALE), new_q.abs()) } else { 0 }
+        } else {
+            let closed = delta.abs().min(old_q.abs());
+            let entry_date = self.entry_dates.get(sym).cloned().unwrap_or_default();
+            let mut removed: Money = 0;
+            if by_lot {
+                let mut lots = self.lots.remove(sym).unwrap_or_default();
+                let mut left = closed;
+                while left > 0 && !lots.is_empty() {
+                    let i = if self.cost_basis == CostBasis::Fifo { 0 } else { lots.len() - 1 };
+                    let take = left.min(lots[i].qty.abs());
+                    // the last lot takes whatever basis is left, so rounding can't strand any
+                    let lot_basis = if lots.len() == 1 && take == lots[0].qty.abs() { old_basis - removed } else { value_of(lots[i].price, take) };
+                    removed += lot_basis;
+                    // exit value of this slice as the difference of running totals, to sum to the fill's
+                    let done = closed - left;
+                    let exit_value = value_of(px, done + take) - value_of(px, done);
+                    let pnl = (exit_value - lot_basis) * old_q.signum();
+                    self.record_close(sym, &lots[i].date, date, take * old_q.signum(), lots[i].price, px, pnl);
+                    lots[i].qty -= take * old_q.signum();
+                    if lots[i].qty == 0 { lots.remove(i); }
+                    left -= take;
+                }
+                if !lots.is_empty() { self.lots.insert(sym.to_string(), lots); }
+            } else {
+                removed = (old_basis as i128 * closed as i128 / old_q.abs() as i128) as Money;
+                let pnl = (value_of(px, closed) - removed) * old_q.signum();
+                self.record_close(sym, &entry_date, date, closed * old_q.signum(), old_avg, px, pnl);
+            }
+            self.basis.insert(sym.to_string(), old_basis - removed);
+
+            if new_q == 0 {
+                self.entry_dates.remove(sym);
+                self.excursions.remove(sym);
+                self.basis.remove(sym);
+                0
+            } else if (new_q > 0) != (old_q > 0) {
+                self.entry_dates.insert(sym.to_string(), date.to_string());
+                self.excursions.insert(sym.to_string(), (px, px));
+                self.basis.insert(sym.to_string(), value_of(px, delta.abs()) - value_of(px, closed));
+                if by_lot {
+                    self.lots.insert(sym.to_string(), vec![Lot { qty: new_q, price: px, date: date.to_string() }]);
+                }
+                px // flipped through zero, the remainder opens at the fill price
+            } else if by_lot {
+                self.lots_avg(sym)
+            } else {
+                old_avg
+            }
+        };
+        self.positions.insert(sym.to_string(), new_q);
+        self.avg_cost.insert(sym.to_string(), new_avg);
+        self.attribute_costs(sym, old_q, delta, slippage, fee, first_close);
+    }
+
+    /// Split a fill's slippage and fee between the qty of a position at `old_q` it closes and
+    /// the qty it opens. The opening share is carried with the position and released pro rata
+    /// as it closes; what this fill releases is spread by qty over the trades it closed,
+    /// `closed_trades[first..]`, so metrics only count the costs of closed qty.
+    fn attribute_costs(&mut self, sym: &str, old_q: Qty, delta: Qty, slippage: Money, fee: Money, first: usize) {
+        let closed = if (old_q > 0) != (delta > 0) { delta.abs().min(old_q.abs()) } else { 0 };
+        let share = |cost: Money, part: Qty, whole: Qty| if whole == 0 { 0 } else { (cost as i128 * part as i128 / whole as i128) as Money };
+        let mut released = [0; 2];
+        for (i, (carried, cost)) in [(&mut self.open_slippage, slippage), (&mut self.open_fees, fee)].into_iter().enumerate() {
+            let held = carried.remove(sym).unwrap_or(0);
+            released[i] = share(held, closed, old_q.abs()) + share(cost, closed, delta.abs());
+            let left = held + cost - released[i];
+            if left != 0 { carried.insert(sym.to_string(), left); }
+        }
+        // the last trade takes whatever is left, so rounding can't strand any
+        let trades = &mut self.closed_trades[first..];
+        let n = trades.len();
+        let mut spread = [0; 2];
+        for (k, t) in trades.iter_mut().enumerate() {
+            let last = k + 1 == n;
+            t.slippage = if last { released[0] - spread[0] } else { share(released[0], t.qty.abs(), closed) };
+            t.fees = if last { released[1] - spread[1] } else { share(released[1], t.qty.abs(), closed) };
+            spread[0] += t.slippage;
+            spread[1] += t.fees;
+        }
+    }
+
+    /// Log `pnl` realized by closing `qty` (signed like the position it closes) bought or sold
+    /// at `entry_price` on average at `exit_price`. The caller prices it off the cost basis:
+    /// exit value less basis for a long, basis less exit value for a short.
+    #[allow(clippy::too_many_arguments)]
+    fn record_close(&mut self, sym: &str, entry_date: &str, exit_date: &str, qty: Qty, entry_price: Money, exit_price: Money, pnl: Money) {
+        self.realized_pnl += pnl;
+        self.trades_closed += 1;
+        if pnl > 0 {
+            self.wins += 1;
+            self.gross_profit += pnl;
+        } else {
+            self.gross_loss -= pnl;
+        }
+        let (mae, mfe) = self.excursion(sym, qty, entry_price);
+        self.closed_trades.push(ClosedTrade {
+            symbol: sym.to_string(),
+            entry_date: entry_date.to_string(),
+            exit_date: exit_date.to_string(),
+            qty,
+            entry_price,
+            exit_price,
+            pnl,
+            mae,
+            mfe,
+            slippage: 0,
+            fees: 0,
+        });
+    }
+
+    /// Widen the (lowest, highest) price range seen by the open position in `sym`.
+    fn touch_excursion(excursions: &mut HashMap<String, (Money, Money)>, sym: &str, px: Money) {
+        let e = excursions.entry(sym.to_string()).or_insert((px, px));
+        e.0 = e.0.min(px);
+        e.1 = e.1.max(px);
+    }
+
+    /// (MAE, MFE) in paise of `qty` (signed) held from `entry_price` over the price range seen
+    /// in `sym`; both are clamped at 0.
+    fn excursion(&self, sym: &str, qty: Qty, entry_price: Money) -> (Money, Money) {
+        let (lo, hi) = *self.excursions.get(sym).unwrap_or(&(entry_price, entry_price));
+        let (adverse, favourable) = if qty > 0 { (entry_price - lo, hi - entry_price) } else { (hi - entry_price, entry_price - lo) };
+        (value_of(adverse.max(0), qty.abs()), value_of(favourable.max(0), qty.abs()))
+    }
+
+    /// Commission on a fill of `qty` shares of `order` at `px`: the Python fee callback if one
+    /// is set, else the maker/taker rate for the order type if set, else the fee model with
+    /// any per-symbol bps. The min fee floors the order's total fee, rebates aside.
+    fn fill_fee(&self, order: &PendingOrder, qty: Qty, px: Money, notional: Money) -> PyResult<Money> {
+        let maker_or_taker = if matches!(order.kind, OrderType::Limit(_)) { self.maker_fee_bps } else { self.taker_fee_bps };
+        let fee = if let Some(f) = &self.fee_fn {
+            Python::with_gil(|py| f.call1(py, (order.symbol.as_str(), order.side.as_str(), qty_to_f64(qty), px))?.extract(py))?
+        } else if let Some(bps) = maker_or_taker {
+            fee_for(notional, bps, self.rounding)
+        } else {
+            self.fee_model.fee(notional, qty, self.fee_bps_by_symbol.get(&order.symbol).copied(), self.rounding, order.fees.is_none())
+        };
+        Ok(if fee < 0 { fee } else { fee.max(self.min_fee - order.fees.unwrap_or(0)) })
+    }
+
+    /// Weighted avg price of the open lots in `sym`, 0 when there are none.
+    fn lots_avg(&self, sym: &str) -> Money {
+        let lots = self.lots.get(sym).map(Vec::as_slice).unwrap_or_default();
+        let q: Qty = lots.iter().map(|l| l.qty.abs()).sum();
+        let notional: Money = lots.iter().map(|l| l.price.saturating_mul(l.qty.abs())).sum();
+        if q != 0 { self.rounding.div(notional, q) } else { 0 }
     }
 }
 
 #[pymodule]
-fn trading_engine(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+fn trading_engine(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<Engine>()?;
     m.add_class::<Metrics>()?;
+    m.add_class::<FillTiming>()?;
+    m.add_class::<FillPriority>()?;
+    m.add_class::<RoundingMode>()?;
+    m.add_class::<DuplicateBars>()?;
+    m.add_class::<CostBasis>()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(open: Money, high: Money, low: Money, close: Money) -> Bar {
+        Bar { date: "2024-01-01".to_string(), symbol: "A".to_string(), open, high, low, close, volume: 1_000 }
+    }
+
+    fn order(side: &str, kind: OrderType) -> PendingOrder {
+        PendingOrder {
+            id: 1, symbol: "A".to_string(), side: side.to_string(), qty: shares(1), kind,
+            bracket: None, oco_with: None, leg: None, tif: "GTC".to_string(), timing: None, fees: None,
+        }
+    }
+
+    #[test]
+    fn stop_outside_the_bar_never_triggers() {
+        let b = bar(100, 110, 90, 105);
+        assert_eq!(fill_price(&b, &order("BUY", OrderType::Stop(111)), 0, 0), None);
+        assert_eq!(fill_price(&b, &order("SELL", OrderType::Stop(89)), 0, 0), None);
+    }
+
+    #[test]
+    fn stop_fills_at_the_trigger_or_a_worse_open() {
+        assert_eq!(fill_price(&bar(100, 110, 90, 105), &order("BUY", OrderType::Stop(105)), 0, 0), Some(105));
+        assert_eq!(fill_price(&bar(115, 120, 112, 118), &order("BUY", OrderType::Stop(105)), 0, 0), Some(115));
+        assert_eq!(fill_price(&bar(100, 110, 90, 105), &order("SELL", OrderType::Stop(95)), 0, 0), Some(95));
+        assert_eq!(fill_price(&bar(85, 88, 80, 86), &order("SELL", OrderType::Stop(95)), 0, 0), Some(85));
+    }
+
+    #[test]
+    fn market_fill_slips_against_the_trade() {
+        let b = bar(10_000, 10_100, 9_900, 10_000);
+        assert_eq!(fill_price(&b, &order("BUY", OrderType::Market), 10, 0), Some(10_010));
+        assert_eq!(fill_price(&b, &order("SELL", OrderType::Market), 10, 0), Some(9_990));
+    }
+
+    #[test]
+    fn limit_fill_never_trades_through_the_limit() {
+        let b = bar(100, 110, 90, 105);
+        assert_eq!(fill_price(&b, &order("BUY", OrderType::Limit(95)), 50, 0), Some(95));
+        assert_eq!(fill_price(&b, &order("BUY", OrderType::Limit(89)), 50, 0), None);
+        assert_eq!(fill_price(&b, &order("SELL", OrderType::Limit(108)), 50, 0), Some(108));
+    }
+
+    #[test]
+    fn day_number_counts_from_the_epoch() {
+        assert_eq!(day_number("1970-01-01"), Some(0));
+        assert_eq!(day_number("2024-01-01"), Some(19_723));
+        assert_eq!(day_number("2024-01-02T09:15:00+05:30"), Some(19_724));
+        assert_eq!(days_between("2024-02-28", "2024-03-01"), 2);
+        assert_eq!(days_between("2023-02-28", "2023-03-01"), 1);
+        assert_eq!(days_between("1999-12-31", "2000-12-31"), 366);
+    }
+
+    #[test]
+    fn day_number_rejects_what_isnt_a_date() {
+        assert_eq!(day_number("2024/01/02"), None);
+        assert_eq!(day_number("2024-13-01"), None);
+        assert_eq!(day_number("d1"), None);
+        assert_eq!(days_between("d1", "2024-01-02"), 0);
+    }
+
+    #[test]
+    fn format_qty_prints_exact_shares() {
+        assert_eq!(format_qty(shares(10)), "10");
+        assert_eq!(format_qty(-500), "-0.5");
+        assert_eq!(format_qty(1_005), "1.005");
+        assert_eq!(format_qty(1_050), "1.05");
+        assert_eq!(format_qty(0), "0");
+    }
+
+    #[test]
+    fn csv_field_quotes_only_when_needed() {
+        assert_eq!(csv_field("RELIANCE"), "RELIANCE");
+        assert_eq!(csv_field("A,B"), "\"A,B\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn rounding_mode_div() {
+        let cases = [(7, 2), (-7, 2), (5, 3), (4, 3), (6, 2)];
+        let got = |mode: RoundingMode| cases.map(|(n, d)| mode.div(n, d));
+        assert_eq!(got(RoundingMode::Truncate), [3, -3, 1, 1, 3]);
+        assert_eq!(got(RoundingMode::Round), [4, -4, 2, 1, 3]);
+        assert_eq!(got(RoundingMode::Ceil), [4, -3, 2, 2, 3]);
+    }
+
+    #[test]
+    fn irr_solves_for_the_rate() {
+        let close = |got: Option<f64>, want: f64| (got.unwrap() - want).abs() < 1e-9;
+        assert!(close(irr(&[(0.0, -100.0), (1.0, 110.0)]), 0.10));
+        assert!(close(irr(&[(0.0, -100.0), (2.0, 121.0)]), 0.10));
+        assert!(close(irr(&[(0.0, -100.0), (0.5, -100.0), (1.0, 200.0)]), 0.0));
+        assert_eq!(irr(&[(0.0, -100.0), (1.0, -5.0)]), None);
+    }
+
+    #[test]
+    fn round_to_tick_rounds_against_the_trade() {
+        assert_eq!(round_to_tick(1_003, 5, "BUY"), 1_005);
+        assert_eq!(round_to_tick(1_003, 5, "SELL"), 1_000);
+        assert_eq!(round_to_tick(1_005, 5, "BUY"), 1_005);
+        assert_eq!(round_to_tick(1_005, 5, "SELL"), 1_005);
+        assert_eq!(round_to_tick(-3, 5, "SELL"), -5);
+    }
+}
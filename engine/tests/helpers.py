@@ -0,0 +1,19 @@
+"""Bar-feeding helpers shared by the engine tests."""
+
+
+def bar(e, date, o, h=None, l=None, c=None, vol=1_000_000, sym="A"):
+    """Feed one bar; the close defaults to the open and high/low to their range."""
+    c = o if c is None else c
+    e.on_bar(date, sym, o, max(o, c) if h is None else h, min(o, c) if l is None else l, c, vol)
+
+
+def close_day(e, date):
+    """Fill pending orders against the day's bars, then mark equity."""
+    e.process_fills_for_date(date)
+    e.end_of_day(date)
+
+
+def step(e, date, o, h=None, l=None, c=None, vol=1_000_000, sym="A"):
+    """A whole day for a single symbol: its bar, fills at it and the end-of-day mark."""
+    bar(e, date, o, h, l, c, vol, sym)
+    close_day(e, date)
@@ -0,0 +1,94 @@
+import os
+import time
+
+import pytest
+import trading_engine as te
+
+from helpers import close_day
+
+
+def rows(n_days, n_symbols):
+    return [
+        (f"2024-01-{d + 1:02d}", f"S{s}", 100 + d, 110 + d, 90 + d, 105 + d, 1_000 + s)
+        for d in range(n_days)
+        for s in range(n_symbols)
+    ]
+
+
+def test_batch_ingest_matches_looping_on_bar():
+    data = rows(5, 3) + [("2024-01-06", "S0", 100, 90, 110, 105, 1_000)]  # one bad bar
+    looped, batched = te.Engine(1_000_000, 0, 0), te.Engine(1_000_000, 0, 0)
+    for e in (looped, batched):
+        e.place_market_order("S1", "BUY", 10)
+    for r in data:
+        looped.on_bar(*r)
+    batched.on_bars_batch(data)
+    for e in (looped, batched):
+        close_day(e, "2024-01-06")
+    assert looped.to_json() == batched.to_json()
+
+
+@pytest.mark.skipif("CI" in os.environ, reason="wall-clock benchmark, too noisy for shared CI runners")
+def test_batch_ingest_is_not_slower_than_the_loop():
+    data = rows(1, 20_000)
+
+    def best_of_three(feed):
+        times = []
+        for _ in range(3):
+            e = te.Engine(1_000_000, 0, 0)
+            t = time.perf_counter()
+            feed(e)
+            times.append(time.perf_counter() - t)
+        return min(times)
+
+    loop = best_of_three(lambda e: [e.on_bar(*r) for r in data])
+    batch = best_of_three(lambda e: e.on_bars_batch(data))
+    assert batch < loop
+
+
+def two_bars_same_day(mode):
+    e = te.Engine(1_000_000, 0, 0, duplicate_bars=mode)
+    e.place_market_order("A", "BUY", 10)
+    e.place_limit_order("A", "BUY", 1, 92)
+    e.on_bar("2024-01-01", "A", 100, 110, 95, 105, 1_000)
+    e.on_bar("2024-01-01", "A", 104, 120, 90, 101, 500)
+    close_day(e, "2024-01-01")
+    return e
+
+
+def test_second_bar_overwrites_by_default():
+    e = two_bars_same_day(te.DuplicateBars.Overwrite)
+    assert [f[4] for f in e.fills()] == [104, 92]
+    assert e.position_value("A") == 11 * 101
+    assert e.duplicate_bar_count() == 1
+
+
+def test_merged_bar_keeps_first_open_and_last_close():
+    e = two_bars_same_day(te.DuplicateBars.Merge)
+    assert [f[4] for f in e.fills()] == [100, 92]
+    assert e.position_value("A") == 11 * 101
+    assert e.duplicate_bar_count() == 1
+
+
+def test_rejected_duplicate_raises_and_keeps_the_first_bar():
+    e = te.Engine(1_000_000, 0, 0, duplicate_bars=te.DuplicateBars.Reject)
+    e.on_bar("2024-01-01", "A", 100, 110, 95, 105, 1_000)
+    with pytest.raises(ValueError):
+        e.on_bar("2024-01-01", "A", 104, 120, 90, 101, 500)
+    e.place_market_order("A", "BUY", 1)
+    close_day(e, "2024-01-01")
+    assert e.fills()[0][4] == 100
+
+
+def test_data_coverage_counts_accepted_bars_and_their_date_range():
+    e = te.Engine(1_000_000, 0, 0)
+    assert (e.bars_seen(), e.symbols_seen(), e.date_range()) == (0, [], ("", ""))
+    e.on_bar("2024-01-03", "B", 100, 100, 100, 100, 10)
+    e.on_bar("2024-01-05", "A", 100, 100, 100, 100, 10)
+    e.on_bar("2024-01-01", "B", 100, 100, 100, 100, 10)
+    # A repeat and a bad bar (low > high) add nothing
+    e.on_bar("2024-01-05", "A", 101, 101, 101, 101, 10)
+    e.on_bar("2023-12-01", "C", 100, 90, 110, 100, 10)
+    assert e.bars_seen() == 3
+    assert e.symbols_seen() == ["A", "B"]
+    assert e.date_range() == ("2024-01-01", "2024-01-05")
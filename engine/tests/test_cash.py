@@ -0,0 +1,104 @@
+import datetime
+
+import pytest
+import trading_engine as te
+
+from helpers import bar, close_day, step
+
+
+def days(n, start=datetime.date(2024, 1, 1)):
+    return [(start + datetime.timedelta(days=i)).isoformat() for i in range(n)]
+
+
+def test_a_year_of_cash_interest_compounds():
+    cash = 1_000_000_000
+    e = te.Engine(cash, 0, 0, annual_cash_rate_bps=500)
+    for d in days(252):
+        e.end_of_day(d)
+    expected = cash * (1 + 0.05 / 252) ** 252
+    # Each accrual truncates to whole paise
+    assert expected - 252 <= e.cash() <= expected
+    assert e.metrics().interest_paise == e.cash() - cash
+
+
+def test_borrow_rate_charges_negative_cash():
+    e = te.Engine(0, 0, 0, annual_borrow_rate_bps=1_000, annual_cash_rate_bps=500)
+    e.place_market_order("A", "BUY", 100)
+    step(e, "2024-01-01", 10_000)
+    step(e, "2024-01-02", 10_000)
+    assert e.cash() == pytest.approx(-1_000_000 * (1 + 0.10 / 252) ** 2, abs=2)
+
+
+def test_sale_proceeds_settle_after_n_marks():
+    e = te.Engine(100_000, 0, 0, reject_on_insufficient_cash=True, settlement_days=2)
+    e.place_market_order("A", "BUY", 1_000)
+    step(e, "2024-01-01", 100)
+    e.place_market_order("A", "SELL", 1_000)
+    step(e, "2024-01-02", 100)
+    # T+2: the sale date's mark counts as the first
+    assert (e.cash(), e.unsettled_cash()) == (100_000, 100_000)
+    early = e.place_market_order("A", "BUY", 500)
+    step(e, "2024-01-03", 100)
+    assert e.rejected_orders() == [(early, "A", "INSUFFICIENT_CASH")]
+    assert e.unsettled_cash() == 0
+    e.place_market_order("A", "BUY", 500)
+    step(e, "2024-01-04", 100)
+    assert e.position("A") == 500
+    assert e.cash() == 50_000
+
+
+def test_settlement_days_must_not_be_negative():
+    with pytest.raises(ValueError):
+        te.Engine(100_000, 0, 0, settlement_days=-1)
+
+
+def test_a_deposit_raises_equity_without_counting_as_a_return():
+    e = te.Engine(100_000, 0, 0)
+    e.place_market_order("A", "BUY", 1_000)
+    step(e, "2024-01-01", 100)
+    e.deposit("2024-01-02", 50_000)
+    step(e, "2024-01-02", 100)
+    assert e.equity_curve()[-1] == ("2024-01-02", 150_000)
+    assert e.twr_pct() == 0.0
+    assert e.metrics().max_drawdown_pct == 0.0
+    step(e, "2024-01-03", 110)
+    assert e.equity_curve()[-1] == ("2024-01-03", 160_000)
+    assert e.twr_pct() == pytest.approx(160_000 / 150_000 * 100 - 100)
+    assert e.cash_flows()[-1] == ("2024-01-02", "DEPOSIT", 50_000)
+
+
+def test_a_withdrawal_is_not_a_drawdown():
+    e = te.Engine(100_000, 0, 0)
+    e.end_of_day("2024-01-01")
+    e.withdraw("2024-01-02", 40_000)
+    e.end_of_day("2024-01-02")
+    e.end_of_day("2024-01-03")
+    assert [v for _, v in e.equity_curve()] == [100_000, 60_000, 60_000]
+    assert e.metrics().max_drawdown_pct == 0.0
+    assert (e.current_drawdown_pct(), e.twr_pct()) == (0.0, 0.0)
+    with pytest.raises(ValueError):
+        e.deposit("2024-01-03", 0)
+
+
+def test_cash_flows_reconcile_to_cash():
+    start = 10_000_000
+    e = te.Engine(start, 0, 5, maker_fee_bps=-2, taker_fee_bps=10, annual_cash_rate_bps=400, allow_short=True)
+
+    def day(date):
+        bar(e, date, 1_000, sym="A")
+        bar(e, date, 2_000, sym="B")
+        close_day(e, date)
+
+    e.place_market_order("A", "BUY", 100)
+    e.place_limit_order("B", "SELL", 50, 2_000)
+    day("2024-01-01")
+    e.apply_dividend("A", "2024-01-01", 7)
+    e.deposit("2024-01-01", 250_000)
+    e.withdraw("2024-01-01", 40_000)
+    e.place_limit_order("A", "SELL", 40, 1_000)
+    e.place_market_order("B", "BUY", 50)
+    day("2024-01-02")
+    day("2024-01-03")
+    flows = e.cash_flows()
+    assert {c for _, c, _ in flows} == {"BUY", "SELL", "FEE", "REBATE", "DIVIDEND", "INTEREST", "DEPOSIT", "WITHDRAWAL"}
+    assert start + sum(delta for _, _, delta in flows) == e.cash()
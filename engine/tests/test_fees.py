@@ -0,0 +1,183 @@
+import pytest
+import trading_engine as te
+
+from helpers import bar, close_day, step
+
+
+def test_per_symbol_fee_override():
+    e = te.Engine(10_000_000, 10, 0)
+    e.set_fee_bps_for_symbol("B", 30)
+    e.place_market_order("A", "BUY", 100)
+    e.place_market_order("B", "BUY", 100)
+    bar(e, "2024-01-01", 1_000, sym="A")
+    bar(e, "2024-01-01", 1_000, sym="B")
+    close_day(e, "2024-01-01")
+    fees = {f[1]: f[5] for f in e.fills()}
+    assert fees == {"A": 100, "B": 300}
+    assert e.metrics().fees_paise == 400
+
+
+def test_min_fee_floors_a_tiny_order():
+    e = te.Engine(10_000_000, 10, 0, min_fee_paise=2_000)
+    e.place_market_order("A", "BUY", 1)
+    e.place_market_order("A", "BUY", 10_000)
+    bar(e, "2024-01-01", 1_000)
+    close_day(e, "2024-01-01")
+    # 10 bps of 1000 paise is 1 paisa, floored to 2000; 10 bps of 10,000,000 is 10,000
+    assert [f[5] for f in e.fills()] == [2_000, 10_000]
+    assert e.metrics().fees_paise == 12_000
+
+
+def fees_under(rounding, qty):
+    e = te.Engine(10_000_000, 10, 0, rounding=rounding)
+    e.place_market_order("A", "BUY", qty)
+    bar(e, "2024-01-01", 100)
+    close_day(e, "2024-01-01")
+    return e.metrics().fees_paise
+
+
+def test_rounding_mode_resolves_a_fractional_fee():
+    # Paise are the smallest unit: 10 bps of 15,500 is 15.5 paise, of 15,100 is 15.1
+    assert [fees_under(te.RoundingMode.Truncate, q) for q in (155, 151)] == [15, 15]
+    assert [fees_under(te.RoundingMode.Round, q) for q in (155, 151)] == [16, 15]
+    assert [fees_under(te.RoundingMode.Ceil, q) for q in (155, 151)] == [16, 16]
+
+
+def test_fees_as_a_percentage_of_pnl_and_notional():
+    e = te.Engine(10_000_000, 10, 0)
+    assert (e.metrics().fees_pct_of_pnl, e.metrics().fees_pct_of_notional) == (0.0, 0.0)
+    e.place_market_order("A", "BUY", 1_000)
+    step(e, "2024-01-01", 1_000)
+    e.place_market_order("A", "SELL", 1_000)
+    step(e, "2024-01-02", 1_100)
+    m = e.metrics()
+    # Fees of 1,000 + 1,100 on 2,100,000 traded and 100,000 realized before fees
+    assert m.fees_paise == 2_100
+    assert m.fees_pct_of_pnl == pytest.approx(2.1)
+    assert m.fees_pct_of_notional == pytest.approx(0.1)
+
+
+def test_negative_maker_rate_is_a_rebate():
+    e = te.Engine(10_000_000, 0, 0, maker_fee_bps=-2, taker_fee_bps=5)
+    e.place_limit_order("A", "BUY", 1_000, 1_000)
+    step(e, "2024-01-01", 1_000)
+    assert e.cash() == 10_000_000 - 1_000_000 + 200
+    e.place_market_order("A", "SELL", 1_000)
+    step(e, "2024-01-02", 1_000)
+    assert [f[5] for f in e.fills()] == [-200, 500]
+    m = e.metrics()
+    assert (m.fees_paise, m.rebates_paise) == (500, 200)
+    assert e.cash() == 10_000_000 - 300
+
+
+def test_min_fee_does_not_shrink_a_rebate():
+    e = te.Engine(10_000_000, -3, 0, min_fee_paise=1_000)
+    e.place_market_order("A", "BUY", 1_000)
+    step(e, "2024-01-01", 1_000)
+    assert (e.metrics().fees_paise, e.metrics().rebates_paise) == (0, 300)
+
+
+def test_costs_reconcile_gross_to_net_realized_pnl():
+    e = te.Engine(10_000_000, 0, 0, slippage_model={"type": "FIXED", "amount": 1}, maker_fee_bps=-2, taker_fee_bps=10)
+    e.place_market_order("A", "BUY", 100)
+    step(e, "2024-01-01", 1_000)
+    e.place_limit_order("A", "SELL", 100, 1_100)
+    step(e, "2024-01-02", 1_100)
+    m = e.metrics()
+    # 1000 -> 1100 before costs; the market buy slips to 1001 and the limit sell doesn't slip
+    assert m.gross_realized_pnl_paise == 10_000
+    assert m.realized_pnl_paise == 9_900
+    assert (m.slippage_paise, m.fees_paise, m.rebates_paise) == (100, 100, 22)
+    assert m.gross_realized_pnl_paise - m.slippage_paise - m.fees_paise + m.rebates_paise == m.net_realized_pnl_paise
+    assert m.net_realized_pnl_paise == e.cash() - 10_000_000
+
+
+def test_open_position_costs_stay_out_of_gross_and_net_realized_pnl():
+    e = te.Engine(10_000_000, 10, 50)
+    e.place_market_order("A", "BUY", 100)
+    step(e, "2024-01-01", 1_000)
+    m = e.metrics()
+    assert (m.slippage_paise, m.fees_paise) == (500, 100)
+    assert m.gross_realized_pnl_paise == m.net_realized_pnl_paise == m.realized_pnl_paise == 0
+
+
+def test_partial_close_releases_its_share_of_the_opening_costs():
+    e = te.Engine(10_000_000, 0, 0, slippage_model={"type": "FIXED", "amount": 1}, taker_fee_bps=10)
+    e.place_market_order("A", "BUY", 100)
+    step(e, "2024-01-01", 1_000)
+    e.place_market_order("A", "SELL", 40)
+    step(e, "2024-01-02", 1_100)
+    m = e.metrics()
+    # 40 of the 100 bought at 1001 for a 100 paise fee, sold at 1099 for a 43 paise fee
+    assert m.realized_pnl_paise == 40 * (1_099 - 1_001)
+    assert m.gross_realized_pnl_paise == 40 * (1_100 - 1_000)
+    assert m.net_realized_pnl_paise == m.realized_pnl_paise - 40 - 43
+    e.place_market_order("A", "SELL", 60)
+    step(e, "2024-01-03", 1_100)
+    m = e.metrics()
+    assert m.gross_realized_pnl_paise - m.slippage_paise - m.fees_paise == m.net_realized_pnl_paise
+    assert m.net_realized_pnl_paise == e.cash() - 10_000_000
+
+
+def test_flat_fee_is_charged_once_per_order():
+    e = te.Engine(10_000_000, 0, 0, max_participation_pct=10.0, fee_model={"type": "FLAT_PER_ORDER", "amount": 2_000})
+    oid = e.place_market_order("A", "BUY", 30)
+    for day in range(1, 4):
+        step(e, f"2024-01-0{day}", 1_000, vol=100)
+    assert [(f[6], f[3], f[5]) for f in e.fills()] == [(oid, 10, 2_000), (oid, 10, 0), (oid, 10, 0)]
+    e.place_market_order("A", "SELL", 5)
+    step(e, "2024-01-04", 1_000, vol=100)
+    assert e.metrics().fees_paise == 4_000
+
+
+def test_min_fee_applies_once_to_an_order_filled_in_parts():
+    e = te.Engine(10_000_000, 10, 0, max_participation_pct=10.0, min_fee_paise=2_000)
+    e.place_market_order("A", "BUY", 30)
+    for day in range(1, 4):
+        step(e, f"2024-01-0{day}", 1_000, vol=100)
+    # 10 bps of each 10,000 paise fill is 10; only the first is topped up to the minimum
+    assert [f[5] for f in e.fills()] == [2_000, 10, 10]
+    assert e.metrics().fees_paise == 2_020
+
+
+def test_fee_callback_overrides_the_model_until_cleared():
+    e = te.Engine(10_000_000, 10, 0, min_fee_paise=20)
+    calls = []
+
+    def fee_fn(symbol, side, qty, price):
+        calls.append((symbol, side, qty, price))
+        return 7 if side == "BUY" else 300
+
+    e.set_fee_fn(fee_fn)
+    e.place_market_order("A", "BUY", 100)
+    step(e, "2024-01-01", 1_000)
+    e.place_market_order("A", "SELL", 50)
+    step(e, "2024-01-02", 1_000)
+    assert calls == [("A", "BUY", 100.0, 1_000), ("A", "SELL", 50.0, 1_000)]
+    # The min fee still floors the callback's fee
+    assert [f[5] for f in e.fills()] == [20, 300]
+    e.set_fee_fn(None)
+    e.place_market_order("A", "SELL", 50)
+    step(e, "2024-01-03", 1_000)
+    # Back to 10 bps of 50,000
+    assert e.fills()[-1][5] == 50
+    with pytest.raises(ValueError):
+        e.set_fee_fn(5)
+
+
+def test_fee_callback_exception_propagates_and_keeps_the_order():
+    e = te.Engine(10_000_000, 10, 0)
+
+    def fee_fn(symbol, side, qty, price):
+        raise RuntimeError("fee service down")
+
+    e.set_fee_fn(fee_fn)
+    oid = e.place_market_order("A", "BUY", 100)
+    bar(e, "2024-01-01", 1_000)
+    with pytest.raises(RuntimeError, match="fee service down"):
+        e.process_fills_for_date("2024-01-01")
+    assert [o[0] for o in e.open_orders()] == [oid]
+    assert e.fills() == [] and e.position("A") == 0
+    e.set_fee_fn(None)
+    e.process_fills_for_date("2024-01-01")
+    assert e.fills()[0][5] == 100
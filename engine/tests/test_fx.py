@@ -0,0 +1,56 @@
+import pytest
+import trading_engine as te
+
+from helpers import step
+
+
+def usd_engine():
+    """AAPL quoted in USD at 83 to the base currency, 84 from 2024-01-02."""
+    e = te.Engine(100_000_000, 0, 0)
+    e.set_symbol_currency("AAPL", "USD")
+    e.set_fx_rate("USD", "2024-01-01", 830_000)
+    e.set_fx_rate("USD", "2024-01-02", 840_000)
+    return e
+
+
+def test_foreign_round_trip_books_pnl_in_base_at_each_days_rate():
+    e = usd_engine()
+    e.place_market_order("AAPL", "BUY", 10)
+    step(e, "2024-01-01", 10_000, sym="AAPL")
+    assert e.fills()[0][4] == 830_000
+    assert e.avg_cost("AAPL") == 830_000
+    assert e.equity_curve()[-1] == ("2024-01-01", 100_000_000)
+    # Unchanged in USD, up 1 rupee a dollar
+    step(e, "2024-01-02", 10_000, sym="AAPL")
+    assert e.equity_curve()[-1] == ("2024-01-02", 100_100_000)
+    e.place_market_order("AAPL", "SELL", 10)
+    step(e, "2024-01-03", 10_000, sym="AAPL")
+    assert e.fills()[1][4] == 840_000
+    assert e.metrics().realized_pnl_paise == 100_000
+    assert e.cash() == 100_100_000
+
+
+def test_missing_fx_rate_raises_before_filling_anything():
+    e = te.Engine(100_000_000, 0, 0)
+    e.set_symbol_currency("SAP", "EUR")
+    oid = e.place_market_order("SAP", "BUY", 10)
+    e.on_bar("2024-01-02", "SAP", 10_000, 10_000, 10_000, 10_000, 1_000)
+    with pytest.raises(ValueError, match="no EUR FX rate"):
+        e.process_fills_for_date("2024-01-02")
+    assert [o[0] for o in e.open_orders()] == [oid] and e.fills() == []
+    e.set_fx_rate("EUR", "2024-01-02", 900_000)
+    e.process_fills_for_date("2024-01-02")
+    assert e.fills()[0][4] == 900_000
+
+
+def test_fx_state_survives_a_checkpoint():
+    e = usd_engine()
+    e.place_market_order("AAPL", "BUY", 10)
+    step(e, "2024-01-01", 10_000, sym="AAPL")
+    restored = te.Engine.from_json(e.to_json())
+    assert restored.to_json() == e.to_json()
+    for engine in (e, restored):
+        engine.place_market_order("AAPL", "SELL", 10)
+        step(engine, "2024-01-02", 10_000, sym="AAPL")
+    assert restored.fills() == e.fills()
+    assert restored.metrics().realized_pnl_paise == e.metrics().realized_pnl_paise == 100_000
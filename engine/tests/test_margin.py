@@ -0,0 +1,65 @@
+import trading_engine as te
+
+from helpers import bar, close_day, step
+
+
+def test_leverage_lets_a_buy_borrow_up_to_the_limit():
+    e = te.Engine(100_000, 0, 0, reject_on_insufficient_cash=True, leverage_limit=2.0)
+    e.place_market_order("A", "BUY", 1_500)
+    step(e, "2024-01-01", 100)
+    assert e.position("A") == 1_500
+    assert e.cash() == -50_000
+    assert e.rejected_orders() == [] and e.margin_calls() == []
+
+
+def test_fill_beyond_the_leverage_limit_is_rejected():
+    e = te.Engine(100_000, 0, 0, leverage_limit=2.0)
+    big = e.place_market_order("A", "BUY", 10_000)
+    step(e, "2024-01-01", 100)
+    assert e.position("A") == 0
+    assert e.rejected_orders() == [(big, "A", "LEVERAGE_LIMIT")]
+
+
+def test_short_sale_counts_toward_gross_exposure():
+    e = te.Engine(100_000, 0, 0, leverage_limit=2.0, allow_short=True)
+    e.place_market_order("A", "BUY", 1_000)
+    step(e, "2024-01-01", 100)
+    short = e.place_market_order("B", "SELL", 1_500)
+    bar(e, "2024-01-02", 100, sym="A")
+    bar(e, "2024-01-02", 100, sym="B")
+    close_day(e, "2024-01-02")
+    assert e.rejected_orders() == [(short, "B", "LEVERAGE_LIMIT")]
+    # Reducing exposure is always allowed
+    e.place_market_order("A", "SELL", 1_000)
+    step(e, "2024-01-03", 100)
+    assert e.position("A") == 0
+
+
+def test_mark_over_the_limit_is_flagged_as_a_margin_call():
+    e = te.Engine(100_000, 0, 0, leverage_limit=2.0)
+    e.place_market_order("A", "BUY", 1_500)
+    step(e, "2024-01-01", 100)
+    for d, px in [("2024-01-02", 80), ("2024-01-03", 70)]:
+        step(e, d, px)
+    assert e.margin_calls() == []
+    step(e, "2024-01-04", 65)
+    # gross 1500 * 65 = 97,500 over equity 97,500 - 50,000 = 47,500 is 2.05x
+    assert e.margin_calls() == [("2024-01-04", 97_500, 47_500)]
+
+
+def test_equity_crossing_zero_flags_a_blow_up():
+    e = te.Engine(10_000, 0, 0, halt_on_blowup=True)
+    e.place_market_order("A", "BUY", 1_000)
+    step(e, "2024-01-01", 100)
+    assert e.blown_up_date() is None
+    step(e, "2024-01-02", 85)
+    step(e, "2024-01-03", 80)
+    assert [v for _, v in e.equity_curve()] == [10_000, -5_000, -10_000]
+    assert e.blown_up_date() == "2024-01-02"
+    m = e.metrics()
+    assert m.blown_up_date == "2024-01-02"
+    assert (m.annual_return_pct, m.sharpe) == (-100.0, 0.0)
+    # Halted: nothing fills after the blow-up
+    e.place_market_order("A", "SELL", 1_000)
+    step(e, "2024-01-04", 90)
+    assert e.position("A") == 1_000
@@ -0,0 +1,290 @@
+import datetime
+import math
+import statistics
+
+import pytest
+import trading_engine as te
+
+from helpers import step
+
+
+def all_in(closes, cash=100_000, **kw):
+    """Buy cash / first close shares on the first bar and mark one day per close."""
+    e = te.Engine(cash, 0, 0, **kw)
+    e.place_market_order("A", "BUY", cash // closes[0])
+    for i, c in enumerate(closes):
+        step(e, f"2024-01-{i + 1:02d}", c)
+    return e
+
+
+def test_calmar_on_a_known_curve():
+    e = all_in([100, 120, 90, 110], periods_per_year=3.0)
+    assert [v for _, v in e.equity_curve()] == [100_000, 120_000, 90_000, 110_000]
+    m = e.metrics()
+    assert m.max_drawdown_pct == pytest.approx(25.0)
+    assert m.annual_return_pct == pytest.approx(10.0)
+    assert m.calmar == pytest.approx(0.4)
+
+
+def test_drawdown_days_on_a_v_shaped_curve():
+    e = all_in([100, 90, 80, 90, 100, 110])
+    assert e.metrics().max_drawdown_days == 4
+
+
+def test_drawdown_days_run_to_the_last_mark_while_still_down():
+    e = all_in([100, 90, 80, 85])
+    assert e.metrics().max_drawdown_days == 3
+
+
+def marked(points, cash=100_000):
+    """All in at the first close, then one mark per (date, close)."""
+    e = te.Engine(cash, 0, 0)
+    e.place_market_order("A", "BUY", cash // points[0][1])
+    for d, c in points:
+        step(e, d, c)
+    return e
+
+
+def test_monthly_returns_chain_across_month_ends():
+    e = marked([("2024-01-01", 100), ("2024-01-31", 110), ("2024-02-01", 120), ("2024-02-28", 130)])
+    (jan, r_jan), (feb, r_feb) = e.monthly_returns()
+    assert (jan, feb) == ("2024-01", "2024-02")
+    assert r_jan == pytest.approx(0.10)
+    assert r_feb == pytest.approx(130 / 110 - 1)
+    assert (1 + r_jan) * (1 + r_feb) == pytest.approx(1.30)
+
+
+def test_one_mark_per_month_still_has_returns():
+    e = marked([("2024-01-31", 100), ("2024-02-29", 110), ("2024-03-31", 99)])
+    assert e.monthly_returns() == [("2024-01", 0.0), ("2024-02", pytest.approx(0.10)), ("2024-03", pytest.approx(-0.10))]
+    assert e.yearly_returns() == [("2024", pytest.approx(-0.01))]
+
+
+def test_strategy_equal_to_benchmark_has_beta_one_and_no_alpha():
+    e = all_in([100, 104, 99, 107, 103, 110])
+    curve = e.equity_curve()
+    e.set_benchmark([d for d, _ in curve], [v for _, v in curve])
+    m = e.metrics()
+    assert m.beta == pytest.approx(1.0)
+    assert m.alpha_annual_pct == pytest.approx(0.0, abs=1e-9)
+    assert m.information_ratio == 0.0
+
+
+def log_returns(e):
+    curve = [v for _, v in e.equity_curve()]
+    return [math.log(b / a) for a, b in zip(curve, curve[1:])]
+
+
+def test_sharpe_and_volatility_at_twelve_periods_a_year():
+    e = all_in([100, 103, 101, 106, 104, 109], periods_per_year=12.0)
+    rets = log_returns(e)
+    mean, sd = statistics.mean(rets), statistics.stdev(rets)
+    m = e.metrics()
+    assert m.volatility == pytest.approx(sd * math.sqrt(12) * 100)
+    assert m.sharpe == pytest.approx(mean / sd * math.sqrt(12))
+    assert m.annual_return_pct == pytest.approx((math.exp(mean * 12) - 1) * 100)
+
+
+def test_ulcer_index_and_pain_ratio_by_hand():
+    e = all_in([100, 120, 90, 110], periods_per_year=3.0)
+    # Percent below the running peak: 0, 0, 25, 100/12
+    ulcer = math.sqrt((25**2 + (100 / 12) ** 2) / 4)
+    m = e.metrics()
+    assert m.ulcer_index == pytest.approx(ulcer)
+    assert m.pain_ratio == pytest.approx(10.0 / ulcer)
+
+
+def test_turnover_and_exposure_on_a_buy_then_sell():
+    e = te.Engine(100_000, 0, 0)
+    e.place_market_order("A", "BUY", 100)
+    step(e, "2024-01-01", 100)
+    e.place_market_order("A", "SELL", 100)
+    step(e, "2024-01-02", 100)
+    m = e.metrics()
+    # 20,000 traded over a mean equity of 100,000 in 2 marks, scaled to 252 a year
+    assert m.turnover_annual == pytest.approx(20_000 / 100_000 * 252 / 2)
+    # 10% invested at the first mark, flat at the second
+    assert m.avg_gross_exposure == pytest.approx(0.05)
+
+
+def test_cagr_of_a_doubling_over_one_year():
+    e = all_in([100, 120, 150, 170, 200], periods_per_year=4.0)
+    m = e.metrics()
+    assert m.cagr_pct == pytest.approx(100.0)
+    assert m.annual_return_pct == pytest.approx(100.0)
+
+
+def test_cagr_of_a_doubling_over_two_years():
+    e = all_in([100, 130, 160, 200], periods_per_year=1.5)
+    assert e.metrics().cagr_pct == pytest.approx((2**0.5 - 1) * 100)
+
+
+def round_trips(outcomes):
+    """One 1-share round trip per outcome, +1 for a win and -1 for a loss, on consecutive days."""
+    e = te.Engine(1_000_000, 0, 0)
+    day = 1
+    for won in outcomes:
+        e.place_market_order("A", "BUY", 1)
+        step(e, f"2024-01-{day:02d}", 100)
+        e.place_market_order("A", "SELL", 1)
+        step(e, f"2024-01-{day + 1:02d}", 101 if won else 99)
+        day += 2
+    return e.metrics()
+
+
+def test_streaks_on_a_crafted_sequence():
+    m = round_trips([True, True, False, True, True, True, False, False])
+    assert (m.max_consecutive_wins, m.max_consecutive_losses) == (3, 2)
+    assert (m.trades_closed, m.win_rate) == (8, 5 / 8)
+
+
+def test_streaks_with_no_losses():
+    m = round_trips([True] * 4)
+    assert (m.max_consecutive_wins, m.max_consecutive_losses) == (4, 0)
+
+
+def daily(start, n):
+    """n consecutive calendar dates from `start`, with a close rising by one a day."""
+    first = datetime.date.fromisoformat(start)
+    return [((first + datetime.timedelta(days=i)).isoformat(), 100 + i) for i in range(n)]
+
+
+def test_monthly_resampling_of_sixty_days():
+    e = marked(daily("2024-01-01", 60))
+    # 31 days of January and all 29 of February
+    assert e.resample_equity("M") == [("2024-01-31", 130_000), ("2024-02-29", 159_000)]
+    months = marked(daily("2024-01-15", 60)).resample_equity("M")
+    assert [d for d, _ in months] == ["2024-01-31", "2024-02-29", "2024-03-14"]
+    assert len(e.resample_equity("W")) == 9
+    with pytest.raises(ValueError):
+        e.resample_equity("D")
+
+
+def test_current_drawdown_tracks_the_running_peak():
+    e = te.Engine(100_000, 0, 0)
+    assert (e.current_drawdown_pct(), e.peak_date(), e.peak_equity()) == (0.0, "", 100_000)
+    e.place_market_order("A", "BUY", 1_000)
+    step(e, "2024-01-01", 100)
+    step(e, "2024-01-02", 80)
+    assert e.current_drawdown_pct() == pytest.approx(20.0)
+    assert e.peak_date() == "2024-01-01"
+    step(e, "2024-01-03", 120)
+    assert e.current_drawdown_pct() == 0.0
+    assert (e.peak_date(), e.peak_equity()) == ("2024-01-03", 120_000)
+    step(e, "2024-01-04", 90)
+    assert e.current_drawdown_pct() == pytest.approx(25.0)
+
+
+def test_metrics_on_an_empty_curve_are_flagged_invalid():
+    m = te.Engine(100_000, 0, 0).metrics()
+    assert not m.is_valid
+    assert (m.sharpe, m.max_drawdown_pct, m.annual_return_pct, m.volatility) == (0.0, 0.0, 0.0, 0.0)
+
+
+def test_two_returns_make_metrics_valid():
+    # Returns run between consecutive marks, so two marks give only one
+    assert not all_in([100]).metrics().is_valid
+    assert not all_in([100, 110]).metrics().is_valid
+    assert all_in([100, 110, 105]).metrics().is_valid
+
+
+def test_twr_is_the_simple_return_without_flows():
+    e = all_in([100, 120, 90, 110])
+    assert e.twr_pct() == pytest.approx(10.0)
+    assert te.Engine(100_000, 0, 0).twr_pct() == 0.0
+
+
+def monthly_year(calendar_aware):
+    """13 month-start marks from 2023-01-01 to 2024-01-01, growing 21% in all."""
+    e = te.Engine(1_000_000, 0, 0, calendar_aware=calendar_aware)
+    e.place_market_order("A", "BUY", 100)
+    for i in range(13):
+        step(e, f"{2023 + i // 12}-{i % 12 + 1:02d}-01", round(10_000 * 1.21 ** (i / 12)))
+    return e
+
+
+def test_calendar_aware_annualizes_a_calendar_year_as_one_year():
+    aware, fixed = monthly_year(True), monthly_year(False)
+    assert aware.equity_curve()[-1] == ("2024-01-01", 1_210_000)
+    # 365 days against 365.25 to the year
+    assert aware.metrics().cagr_pct == pytest.approx(100 * (1.21 ** (365.25 / 365) - 1))
+    assert fixed.metrics().cagr_pct == pytest.approx(100 * (1.21 ** (252 / 12) - 1))
+    ratio = aware.metrics().volatility / fixed.metrics().volatility
+    assert ratio == pytest.approx(math.sqrt(12 * 365.25 / 365 / 252))
+
+
+def test_omega_on_a_hand_computed_sample():
+    closes = [1_000, 1_100, 990, 1_089]
+    # Log returns ln 1.1, ln 0.9, ln 1.1
+    assert all_in(closes).metrics().omega == pytest.approx(2 * math.log(1.1) / -math.log(0.9))
+    t = math.log(1.05)
+    expected = 2 * (math.log(1.1) - t) / (t - math.log(0.9))
+    assert all_in(closes, omega_threshold_pct=5.0).metrics().omega == pytest.approx(expected)
+    assert all_in([1_000, 1_100, 1_210]).metrics().omega == 0.0
+
+
+def test_holding_period_histogram_buckets_known_durations():
+    e = te.Engine(1_000_000, 0, 0)
+    day = datetime.date(2024, 1, 1)
+    for held in [1, 1, 3, 7, 30, 300]:
+        e.place_market_order("A", "BUY", 1)
+        step(e, day.isoformat(), 100)
+        day += datetime.timedelta(days=held)
+        e.place_market_order("A", "SELL", 1)
+        step(e, day.isoformat(), 100)
+        day += datetime.timedelta(days=1)
+    assert e.holding_period_histogram() == [
+        (0, 0), (1, 2), (2, 1), (5, 1), (10, 0), (20, 1), (60, 0), (120, 0), (250, 1),
+    ]
+    assert e.holding_period_histogram([2, 10]) == [(2, 2), (10, 2)]
+    with pytest.raises(ValueError):
+        e.holding_period_histogram([5, 5])
+
+
+def test_drawdown_episodes_on_a_curve_with_two_drawdowns():
+    e = all_in([100, 90, 80, 100, 110, 99, 105])
+    first = ("2024-01-01", "2024-01-03", "2024-01-04", pytest.approx(20.0))
+    second = ("2024-01-05", "2024-01-06", "ongoing", pytest.approx(10.0))
+    assert e.drawdown_episodes(0.0) == [first, second]
+    assert e.drawdown_episodes(15.0) == [first]
+    m = e.metrics()
+    assert m.max_drawdown_paise == 20_000
+    # 5,000 net profit over the 20,000 deepest drawdown
+    assert m.recovery_factor == pytest.approx(0.25)
+
+
+def test_benchmark_identical_to_the_strategy_has_no_relative_drawdown():
+    closes = [100, 120, 90, 110, 95]
+    e = all_in(closes)
+    dates = [d for d, _ in e.equity_curve()]
+    e.set_benchmark(dates, closes)
+    assert e.relative_drawdown_curve() == [(d, pytest.approx(0.0)) for d in dates[1:]]
+    assert e.metrics().max_relative_drawdown_pct == pytest.approx(0.0)
+    # Against a flat benchmark the relative drawdown is the strategy's own
+    e.set_benchmark(dates, [100] * len(dates))
+    assert e.metrics().max_relative_drawdown_pct == pytest.approx(e.metrics().max_drawdown_pct)
+
+
+def test_warmup_is_left_out_of_metrics_but_not_the_curve():
+    e = te.Engine(100_000, 0, 0)
+    # A losing round trip and flat days during the warmup, then a run of gains and losses
+    e.place_market_order("A", "BUY", 100)
+    step(e, "2024-01-01", 100)
+    e.place_market_order("A", "SELL", 100)
+    step(e, "2024-01-02", 90)
+    for day in range(3, 8):
+        step(e, f"2024-01-{day:02d}", 90)
+    e.place_market_order("A", "BUY", 1_000)
+    for day, c in zip(range(8, 14), [90, 95, 93, 99, 97, 104]):
+        step(e, f"2024-01-{day:02d}", c)
+    full = e.metrics()
+    curve = e.equity_curve()
+    e.set_warmup_until("2024-01-08")
+    warm = e.metrics()
+    assert e.equity_curve() == curve
+    assert (full.trades_closed, warm.trades_closed) == (1, 0)
+    assert warm.cagr_pct > full.cagr_pct
+    assert warm.sharpe != pytest.approx(full.sharpe)
+    assert warm.volatility > full.volatility
+    e.set_warmup_until(None)
+    assert e.metrics().sharpe == full.sharpe
@@ -0,0 +1,275 @@
+import pytest
+import trading_engine as te
+
+from helpers import step
+
+
+def test_stop_above_the_range_never_triggers():
+    e = te.Engine(1_000_000, 0, 0)
+    oid = e.place_stop_order("A", "BUY", 5, 200, "GTC")
+    step(e, "2024-01-01", 100, 110, 90, 105)
+    step(e, "2024-01-02", 150, 199, 140, 180)
+    assert e.fills() == []
+    assert [o[0] for o in e.open_orders()] == [oid]
+
+
+def test_sell_stop_triggers_on_an_intrabar_breach():
+    e = te.Engine(1_000_000, 0, 0)
+    e.place_market_order("A", "BUY", 5)
+    step(e, "2024-01-01", 100)
+    e.place_stop_order("A", "SELL", 5, 95, "GTC")
+    step(e, "2024-01-02", 100, 101, 96, 100)
+    assert len(e.fills()) == 1
+    step(e, "2024-01-03", 100, 102, 90, 99)
+    sell = e.fills()[-1]
+    assert (sell[2], sell[3], sell[4]) == ("SELL", 5.0, 95)
+    assert e.position("A") == 0
+
+
+def test_trailing_stop_ratchets_up_but_never_down():
+    e = te.Engine(1_000_000, 0, 0)
+    e.place_market_order("A", "BUY", 10)
+    step(e, "2024-01-01", 100)
+    e.place_trailing_stop("A", "SELL", 10, 1_000)
+
+    def level():
+        return e.open_orders()[0][5]
+
+    assert level() == 90
+    step(e, "2024-01-02", 110, 120, 109, 115)
+    assert level() == 108
+    step(e, "2024-01-03", 112, 113, 109, 110)
+    assert level() == 108
+    assert e.position("A") == 10
+    step(e, "2024-01-04", 107, 109, 100, 101)
+    assert e.fills()[-1][2:5] == ("SELL", 10.0, 107)
+    assert e.open_orders() == []
+
+
+def test_cancel_one_of_several_pending_orders():
+    e = te.Engine(1_000_000, 0, 0)
+    ids = [e.place_limit_order("A", "BUY", 1, px, "GTC") for px in (90, 91, 92)]
+    assert e.cancel_order(ids[1]) is True
+    assert [o[0] for o in e.open_orders()] == [ids[0], ids[2]]
+    assert e.cancel_order(ids[1]) is False
+
+
+def test_cancelling_a_filled_order_returns_false():
+    e = te.Engine(1_000_000, 0, 0)
+    oid = e.place_market_order("A", "BUY", 1)
+    step(e, "2024-01-01", 100)
+    assert len(e.fills()) == 1
+    assert e.cancel_order(oid) is False
+    assert e.cancel_order(12_345) is False
+
+
+def test_order_target_notional_moves_to_the_target():
+    e = te.Engine(1_000_000, 0, 0)
+    step(e, "2024-01-01", 100)
+    assert e.order_target_notional("A", "BUY", 1_050) == 10
+    step(e, "2024-01-02", 100)
+    assert e.position("A") == 10
+    assert e.order_target_notional("A", "BUY", 400) == -6
+    step(e, "2024-01-03", 100)
+    assert e.position("A") == 4
+
+
+def test_order_target_notional_closes_a_fractional_position():
+    e = te.Engine(1_000_000, 0, 0)
+    e.place_market_order_fractional("A", "BUY", 0.5)
+    step(e, "2024-01-01", 100)
+    assert e.order_target_notional("A", "BUY", 0) == -0.5
+    step(e, "2024-01-02", 100)
+    assert e.position_fractional("A") == 0.0
+
+
+def test_order_target_notional_rejects_a_bad_side():
+    e = te.Engine(1_000_000, 0, 0)
+    step(e, "2024-01-01", 100)
+    with pytest.raises(ValueError, match="side must be BUY or SELL"):
+        e.order_target_notional("A", "bogus", 1_000)
+    with pytest.raises(ValueError, match="side must be BUY or SELL"):
+        e.order_target_percent("A", "bogus", 10.0)
+    assert e.open_orders() == []
+
+
+def fill_order(priority):
+    e = te.Engine(1_000, 0, 0, reject_on_insufficient_cash=True, fill_priority=priority)
+    e.place_market_order("A", "BUY", 10)
+    step(e, "2024-01-01", 100)
+    buy = e.place_market_order("A", "BUY", 10)
+    sell = e.place_market_order("A", "SELL", 10)
+    step(e, "2024-01-02", 100)
+    return e, buy, sell
+
+
+def test_fifo_fills_by_order_id():
+    e, buy, sell = fill_order(te.FillPriority.FifoByOrderId)
+    assert e.rejected_orders() == [(buy, "A", "INSUFFICIENT_CASH")]
+    assert [f[6] for f in e.fills()[1:]] == [sell]
+
+
+def test_sells_first_frees_cash_for_the_buy_on_the_same_bar():
+    e, buy, sell = fill_order(te.FillPriority.SellsFirst)
+    assert e.rejected_orders() == []
+    assert [f[6] for f in e.fills()[1:]] == [sell, buy]
+    assert e.position("A") == 10
+
+
+def test_identical_engines_produce_identical_fills():
+    def run():
+        e = te.Engine(10_000_000, 5, 3, max_participation_pct=10.0, fill_priority=te.FillPriority.SellsFirst)
+        e.place_market_order("A", "BUY", 500)
+        step(e, "2024-01-01", 1_000, vol=100_000)
+        # Orders competing for the same capped bar, partial fills carried over
+        for side, qty in [("BUY", 300), ("SELL", 200), ("BUY", 300), ("SELL", 250)]:
+            e.place_market_order("A", side, qty)
+        for i, px in enumerate([1_010, 990, 1_005]):
+            step(e, f"2024-01-{i + 2:02d}", px, vol=2_000)
+        return e.fills()
+
+    first = run()
+    assert len(first) > 5
+    assert run() == first
+
+
+def test_position_limit_truncates_and_records_the_cut():
+    e = te.Engine(1_000_000, 0, 0)
+    e.set_max_position_qty("A", 50)
+    oid = e.place_market_order("A", "BUY", 80)
+    step(e, "2024-01-01", 100)
+    assert e.position("A") == 50
+    assert e.truncated_orders() == [(oid, "A", "2024-01-01", 30.0)]
+    assert e.open_orders() == []
+
+
+def test_position_limit_rejects_when_configured():
+    e = te.Engine(1_000_000, 0, 0, reject_over_position_limit=True)
+    e.set_max_position_qty("A", 50)
+    oid = e.place_market_order("A", "BUY", 80)
+    step(e, "2024-01-01", 100)
+    assert e.position("A") == 0
+    assert e.rejected_orders() == [(oid, "A", "POSITION_LIMIT")]
+    assert e.truncated_orders() == []
+
+
+def test_bad_order_arguments_raise():
+    e = te.Engine(1_000_000, 0, 0)
+    with pytest.raises(ValueError, match="qty must be positive"):
+        e.place_market_order("A", "BUY", -10)
+    with pytest.raises(ValueError, match="qty must be positive"):
+        e.place_limit_order("A", "SELL", 0, 100)
+    with pytest.raises(ValueError, match="qty must be positive"):
+        e.place_market_order_fractional("A", "BUY", 0.0004)
+    with pytest.raises(ValueError, match="side must be BUY or SELL"):
+        e.place_stop_order("A", "buy", 1, 100)
+    with pytest.raises(ValueError, match="tif must be GTC or DAY"):
+        e.place_market_order("A", "BUY", 1, "IOC")
+    assert e.open_orders() == []
+
+
+def test_fills_in_range_includes_both_ends():
+    e = te.Engine(1_000_000, 0, 0)
+    for i in range(1, 6):
+        e.place_market_order("A" if i % 2 else "B", "BUY", i)
+        step(e, f"2024-01-0{i}", 100, sym="A" if i % 2 else "B")
+    dates = [f[0] for f in e.fills_in_range("2024-01-02", "2024-01-04")]
+    assert dates == ["2024-01-02", "2024-01-03", "2024-01-04"]
+    assert e.fills_in_range("2024-01-05", "2024-01-05")[0][3] == 5.0
+    assert e.fills_in_range("2024-01-06", "2024-01-09") == []
+    assert [f[3] for f in e.fills_for_symbol("B")] == [2.0, 4.0]
+    assert e.fills_for_symbol("C") == []
+
+
+def test_preview_matches_the_fill_it_predicts():
+    e = te.Engine(10_000_000, 12, 7, impact_bps=50.0)
+    step(e, "2024-01-01", 10_000, c=10_100, vol=20_000)
+    for side, qty in [("BUY", 400), ("SELL", 100)]:
+        price, fee = e.preview_fill("A", side, qty)
+        e.place_market_order("A", side, qty)
+        # Under NextOpen the preview is exact when the next bar opens where the last one did
+        step(e, "2024-01-02", 10_000, c=10_100, vol=20_000)
+        assert e.fills()[-1][4:6] == (price, fee)
+
+
+def test_preview_at_the_close_under_same_close():
+    e = te.Engine(10_000_000, 12, 7, fill_timing=te.FillTiming.SameClose)
+    e.on_bar("2024-01-01", "A", 10_000, 10_200, 9_900, 10_100, 20_000)
+    price, fee = e.preview_fill("A", "BUY", 50)
+    assert e.open_orders() == [] and e.fills() == []
+    e.place_market_order("A", "BUY", 50)
+    e.end_of_day("2024-01-01")
+    assert e.fills()[0][4:6] == (price, fee)
+    with pytest.raises(ValueError):
+        e.preview_fill("B", "BUY", 1)
+
+
+def test_odd_lot_is_cut_to_whole_lots():
+    e = te.Engine(1_000_000, 0, 0)
+    e.set_lot_size("A", 100)
+    oid = e.place_market_order("A", "BUY", 150)
+    small = e.place_market_order("A", "BUY", 99)
+    step(e, "2024-01-01", 100)
+    assert e.position("A") == 100
+    assert e.lot_adjustments() == [(oid, "A", "2024-01-01", 50.0)]
+    assert e.rejected_orders() == [(small, "A", "BELOW_LOT_SIZE")]
+    with pytest.raises(ValueError):
+        e.set_lot_size("A", 0)
+
+
+def test_zero_qty_places_nothing_and_closes_no_trade():
+    e = te.Engine(1_000_000, 0, 0)
+    e.place_market_order("A", "BUY", 10)
+    step(e, "2024-01-01", 100)
+    e.place_market_order("A", "SELL", 10)
+    step(e, "2024-01-02", 110)
+    before = (len(e.fills()), e.metrics().trades_closed)
+    with pytest.raises(ValueError):
+        e.place_market_order("A", "SELL", 0)
+    step(e, "2024-01-03", 120)
+    assert (len(e.fills()), e.metrics().trades_closed) == before == (2, 1)
+    assert e.open_orders() == [] and e.metrics().win_rate == 1.0
+
+
+def test_moc_fills_at_the_close_and_moo_at_the_open_of_the_same_bar():
+    e = te.Engine(1_000_000, 0, 10)
+    moo = e.place_market_order("A", "BUY", 10, timing="MOO")
+    moc = e.place_market_order("A", "BUY", 10, timing="MOC")
+    step(e, "2024-01-01", 1_000, 1_100, 950, 1_050)
+    # 10 bps of slippage on each reference
+    assert {f[6]: f[4] for f in e.fills()} == {moo: 1_001, moc: 1_051}
+    with pytest.raises(ValueError, match="timing must be MOO or MOC"):
+        e.place_market_order("A", "BUY", 1, timing="NEXT")
+    same_close = te.Engine(1_000_000, 0, 0, fill_timing=te.FillTiming.SameClose)
+    with pytest.raises(ValueError):
+        same_close.place_market_order("A", "BUY", 1, timing="MOO")
+
+
+def test_participation_cap_fills_a_limit_over_three_bars():
+    e = te.Engine(10_000_000, 0, 0, max_participation_pct=10.0)
+    oid = e.place_limit_order("A", "BUY", 1_000, 105)
+    assert e.order_fill_summary(oid) is None
+    for date, px, vol in [("2024-01-01", 100, 3_000), ("2024-01-02", 98, 3_000), ("2024-01-03", 99, 5_000)]:
+        step(e, date, px, vol=vol)
+    assert [(f[3], f[4]) for f in e.fills()] == [(300, 100), (300, 98), (400, 99)]
+    assert e.open_orders() == []
+    assert e.order_fill_summary(oid) == (1_000.0, pytest.approx(99.0))
+
+
+def test_tick_rounding_is_against_the_trade_and_previewed():
+    e = te.Engine(10_000_000, 10, 30)
+    e.set_tick_size("A", 5)
+    step(e, "2024-01-01", 1_000)
+    for side, want in [("BUY", 1_005), ("SELL", 995)]:
+        price, fee = e.preview_fill("A", side, 100)
+        # 30 bps slips 1000 to 1003 and 997, then the tick rounds against the trade
+        assert price == want
+        e.place_market_order("A", side, 100)
+        step(e, "2024-01-02", 1_000)
+        assert e.fills()[-1][4:6] == (price, fee)
+    limit = e.place_limit_order("A", "BUY", 10, 1_002)
+    step(e, "2024-01-03", 1_001)
+    # Rounding up to 1005 would go through the limit
+    assert [f[4] for f in e.fills() if f[6] == limit] == [1_002]
+    with pytest.raises(ValueError):
+        e.set_tick_size("A", 0)
@@ -0,0 +1,48 @@
+"""Randomized check that realized + unrealized PnL accounts for every paisa of equity change."""
+import random
+
+import trading_engine as te
+
+START = 10**12
+SYMBOLS = ("A", "B")
+
+
+def random_run(rng, cost_basis, days=40):
+    e = te.Engine(START, 0, 0, cost_basis=cost_basis, allow_short=True)
+    px = {s: rng.randint(1_000, 20_000) for s in SYMBOLS}
+    for i in range(days):
+        d = f"2024-{1 + i // 28:02d}-{1 + i % 28:02d}"
+        for s in SYMBOLS:
+            o = max(100, px[s] + rng.randint(-300, 300))
+            c = max(100, o + rng.randint(-300, 300))
+            e.on_bar(d, s, o, max(o, c) + rng.randint(0, 50), min(o, c) - rng.randint(0, 50), c, 10**9)
+            px[s] = c
+        e.process_fills_for_date(d)
+        for s in SYMBOLS:
+            side = rng.choice(["BUY", "SELL"])
+            if rng.random() < 0.3:
+                e.place_market_order_fractional(s, side, rng.randint(1, 50) + rng.random())
+            else:
+                e.place_market_order(s, side, rng.randint(1, 50))
+        e.end_of_day(d)
+        yield e, d
+
+
+def check(cost_basis, seed, runs=100):
+    rng = random.Random(seed)
+    for _ in range(runs):
+        for e, d in random_run(rng, cost_basis):
+            pnl = e.metrics().realized_pnl_paise + e.unrealized_pnl(d)
+            assert e.current_equity() - START == pnl, (cost_basis, d, e.fills()[-3:])
+
+
+def test_average_cost_reconciles_on_random_sequences():
+    check(te.CostBasis.Average, 1)
+
+
+def test_fifo_reconciles_on_random_sequences():
+    check(te.CostBasis.Fifo, 2)
+
+
+def test_lifo_reconciles_on_random_sequences():
+    check(te.CostBasis.Lifo, 3)
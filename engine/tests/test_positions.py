@@ -0,0 +1,227 @@
+import pytest
+import trading_engine as te
+
+from helpers import bar, close_day, step
+
+
+def test_short_round_trip_at_a_profit():
+    e = te.Engine(100_000, 0, 0, allow_short=True)
+    e.place_market_order("A", "SELL", 10)
+    step(e, "2024-01-01", 100)
+    assert e.position("A") == -10
+    assert e.cash() == 101_000
+    e.place_market_order("A", "BUY", 10)
+    step(e, "2024-01-02", 80)
+    m = e.metrics()
+    assert e.position("A") == 0
+    assert m.realized_pnl_paise == 200
+    assert (m.trades_closed, m.win_rate) == (1, 1.0)
+    assert e.cash() == 100_200
+
+
+def test_short_round_trip_at_a_loss():
+    e = te.Engine(100_000, 0, 0, allow_short=True)
+    e.place_market_order("A", "SELL", 10)
+    step(e, "2024-01-01", 80)
+    e.place_market_order("A", "BUY", 10)
+    step(e, "2024-01-02", 90)
+    m = e.metrics()
+    assert e.position("A") == 0
+    assert m.realized_pnl_paise == -100
+    assert (m.trades_closed, m.win_rate) == (1, 0.0)
+    assert e.cash() == 99_900
+
+
+def test_long_only_sell_is_clamped_to_the_position():
+    e = te.Engine(100_000, 0, 0)
+    e.place_market_order("A", "BUY", 10)
+    step(e, "2024-01-01", 100)
+    over = e.place_market_order("A", "SELL", 25)
+    step(e, "2024-01-02", 100)
+    assert e.position("A") == 0
+    assert e.fills()[-1][3] == 10
+    assert e.truncated_orders() == [(over, "A", "2024-01-02", 15.0)]
+    naked = e.place_market_order("A", "SELL", 5)
+    step(e, "2024-01-03", 100)
+    assert e.rejected_orders() == [(naked, "A", "SHORT_NOT_ALLOWED")]
+    assert (e.position("A"), len(e.fills())) == (0, 2)
+
+
+def test_allow_short_sells_through_to_a_short():
+    e = te.Engine(100_000, 0, 0, allow_short=True)
+    e.place_market_order("A", "BUY", 10)
+    step(e, "2024-01-01", 100)
+    e.place_market_order("A", "SELL", 25)
+    step(e, "2024-01-02", 100)
+    assert e.position("A") == -15
+    assert e.truncated_orders() == [] and e.rejected_orders() == []
+
+def test_half_share_position_is_valued_at_end_of_day():
+    e = te.Engine(100_000, 0, 0)
+    e.place_market_order_fractional("A", "BUY", 0.5, "GTC")
+    step(e, "2024-01-01", 10_000, c=10_050)
+    assert e.position_fractional("A") == 0.5
+    assert e.position("A") == 0
+    assert e.cash() == 95_000
+    assert e.position_value("A") == 5_025
+    assert e.equity_curve()[-1] == ("2024-01-01", 100_025)
+
+
+def test_fractional_qty_keeps_three_decimals():
+    e = te.Engine(100_000, 0, 0)
+    e.place_market_order_fractional("A", "BUY", 0.0014, "GTC")
+    step(e, "2024-01-01", 10_000)
+    assert e.position_fractional("A") == 0.001
+
+
+def test_two_for_one_split_halves_avg_cost():
+    e = te.Engine(100_000, 0, 0)
+    e.place_market_order("A", "BUY", 100)
+    step(e, "2024-01-01", 200)
+    e.apply_split("A", 2, 1)
+    assert e.position("A") == 200
+    assert e.avg_cost("A") == 100
+    step(e, "2024-01-02", 100)
+    assert e.metrics().realized_pnl_paise == 0
+    assert e.unrealized_pnl("2024-01-02") == 0
+    assert [v for _, v in e.equity_curve()] == [100_000, 100_000]
+
+
+def test_equity_is_unchanged_right_after_a_split():
+    e = te.Engine(10_000_000, 0, 0)
+    e.place_market_order("A", "BUY", 100)
+    step(e, "2024-01-01", 200)
+    before = e.current_equity()
+    e.apply_split("A", 2, 1)
+    assert e.current_equity() == before
+    assert e.position_value("A") == 200 * 100
+
+
+def test_resting_orders_are_restated_across_a_split():
+    e = te.Engine(10_000_000, 0, 0)
+    e.place_market_order("A", "BUY", 100)
+    step(e, "2024-01-01", 200)
+    stop = e.place_stop_order("A", "SELL", 100, 190)
+    e.apply_split("A", 2, 1)
+    assert e.open_orders() == [(stop, "A", "SELL", 200.0, "STOP", 95)]
+    # 100 would have triggered the old 190 stop; it is above the new 95
+    step(e, "2024-01-02", 100)
+    assert e.position("A") == 200
+    step(e, "2024-01-03", 94)
+    assert e.position("A") == 0
+
+
+def test_split_does_not_look_like_a_gap():
+    e = te.Engine(10_000_000, 0, 0, gap_slippage_coeff=1.0, gap_threshold_bps=100)
+    step(e, "2024-01-01", 20_000)
+    step(e, "2024-01-02", 20_000)
+    e.apply_split("A", 2, 1)
+    e.place_market_order("A", "BUY", 10)
+    step(e, "2024-01-03", 10_000)
+    assert e.fills()[0][4] == 10_000
+    assert e.metrics().slippage_paise == 0
+
+def test_liquidate_all_flattens_and_realizes_the_final_move():
+    e = te.Engine(1_000_000, 0, 0, allow_short=True)
+    e.place_market_order("A", "BUY", 10)
+    e.place_market_order("B", "SELL", 5)
+    bar(e, "2024-01-01", 100, sym="A")
+    bar(e, "2024-01-01", 200, sym="B")
+    close_day(e, "2024-01-01")
+    ids = e.liquidate_all()
+    assert len(ids) == 2
+    bar(e, "2024-01-02", 130, sym="A")
+    bar(e, "2024-01-02", 180, sym="B")
+    close_day(e, "2024-01-02")
+    assert (e.position("A"), e.position("B")) == (0, 0)
+    # 10 * (130 - 100) on the long, 5 * (200 - 180) on the short
+    assert e.metrics().realized_pnl_paise == 400
+    assert e.equity_curve()[-1][1] == e.cash() == 1_000_400
+
+
+def two_lots_then_partial_sell(cost_basis):
+    e = te.Engine(1_000_000, 0, 0, cost_basis=cost_basis)
+    e.place_market_order("A", "BUY", 10)
+    step(e, "2024-01-01", 100)
+    e.place_market_order("A", "BUY", 10)
+    step(e, "2024-01-02", 200)
+    e.place_market_order("A", "SELL", 10)
+    step(e, "2024-01-03", 250)
+    return e
+
+
+def test_fifo_and_average_diverge_on_a_partial_sell():
+    avg = two_lots_then_partial_sell(te.CostBasis.Average)
+    fifo = two_lots_then_partial_sell(te.CostBasis.Fifo)
+    assert avg.metrics().realized_pnl_paise == 10 * (250 - 150)
+    assert fifo.metrics().realized_pnl_paise == 10 * (250 - 100)
+    assert (avg.avg_cost("A"), fifo.avg_cost("A")) == (150, 200)
+    # Same marks either way: only the split between realized and unrealized differs
+    assert avg.equity_curve() == fifo.equity_curve()
+    assert avg.unrealized_pnl("2024-01-03") == 10 * (250 - 150)
+    assert fifo.unrealized_pnl("2024-01-03") == 10 * (250 - 200)
+
+
+def test_avg_cost_after_a_weighted_average_buy():
+    e = te.Engine(1_000_000, 0, 0)
+    e.place_market_order("A", "BUY", 10)
+    step(e, "2024-01-01", 100)
+    assert e.avg_cost("A") == 100
+    e.place_market_order("A", "BUY", 30)
+    step(e, "2024-01-02", 200)
+    assert e.avg_cost("A") == (10 * 100 + 30 * 200) // 40
+    e.place_market_order("A", "SELL", 40)
+    step(e, "2024-01-03", 200)
+    assert e.avg_cost("A") == 0
+
+
+def test_balanced_long_short_book_is_dollar_neutral():
+    e = te.Engine(1_000_000, 0, 0, allow_short=True)
+    e.place_market_order("A", "BUY", 100)
+    e.place_market_order("B", "SELL", 200)
+    for date, a, b in [("2024-01-01", 1_000, 500), ("2024-01-02", 1_010, 505), ("2024-01-03", 1_020, 500)]:
+        bar(e, date, a, sym="A")
+        bar(e, date, b, sym="B")
+        close_day(e, date)
+    assert e.exposure_curve() == [
+        ("2024-01-01", 0, 200_000),
+        ("2024-01-02", 0, 202_000),
+        ("2024-01-03", 2_000, 202_000),
+    ]
+    # Net stays within 1% of gross
+    assert all(abs(net) <= gross / 100 for _, net, gross in e.exposure_curve())
+
+
+def test_portfolio_snapshot_of_a_two_symbol_book():
+    e = te.Engine(1_000_000, 0, 0, allow_short=True)
+    e.place_market_order("B", "BUY", 10)
+    e.place_market_order("A", "SELL", 20)
+    bar(e, "2024-01-01", 1_000, sym="A")
+    bar(e, "2024-01-01", 500, sym="B")
+    close_day(e, "2024-01-01")
+    bar(e, "2024-01-02", 1_100, sym="A")
+    bar(e, "2024-01-02", 450, sym="B")
+    close_day(e, "2024-01-02")
+    assert e.portfolio_snapshot("2024-01-02") == [("A", -20.0, 1_000, -22_000), ("B", 10.0, 500, 4_500)]
+    with pytest.raises(ValueError):
+        e.portfolio_snapshot("2024-01-01")
+
+
+def test_realized_pnl_by_symbol_sums_to_the_total():
+    e = te.Engine(10_000_000, 5, 0, allow_short=True)
+    days = [
+        ("2024-01-01", {"A": 1_000, "B": 2_000, "C": 500}, [("A", "BUY", 30), ("B", "SELL", 20), ("C", "BUY", 10)]),
+        ("2024-01-02", {"A": 1_100, "B": 2_100, "C": 480}, [("A", "SELL", 10), ("B", "BUY", 5)]),
+        ("2024-01-03", {"A": 960, "B": 1_900, "C": 520}, [("A", "SELL", 20), ("B", "BUY", 15), ("C", "SELL", 10)]),
+        ("2024-01-04", {"A": 1_000, "B": 2_000, "C": 500}, []),
+    ]
+    for date, closes, orders in days:
+        for sym, side, qty in orders:
+            e.place_market_order(sym, side, qty)
+        for sym, px in closes.items():
+            bar(e, date, px, sym=sym)
+        close_day(e, date)
+    by_symbol = e.realized_pnl_by_symbol()
+    # Fees don't count: A gains 100 on 10 and loses 40 on 20; short B loses 100 on 5 and gains 100 on 15
+    assert by_symbol == [("A", 200), ("B", 1_000), ("C", 200)]
+    assert sum(pnl for _, pnl in by_symbol) == e.metrics().realized_pnl_paise
@@ -0,0 +1,91 @@
+import trading_engine as te
+
+from helpers import bar, close_day, step
+
+
+def test_orders_after_the_daily_loss_limit_are_refused_until_the_next_day():
+    e = te.Engine(100_000, 0, 0, daily_loss_limit_paise=5_000)
+    e.place_market_order("A", "BUY", 1_000)
+    step(e, "2024-01-01", 100)
+    # A 5 paise drop on 1000 shares is exactly the limit, which is still allowed
+    bar(e, "2024-01-02", 95)
+    at_limit = e.place_market_order("A", "BUY", 1)
+    bar(e, "2024-01-02", 94)
+    refused = e.place_market_order("A", "BUY", 1)
+    assert e.rejected_orders() == [(refused, "A", "DAILY_LOSS_LIMIT")]
+    assert [o[0] for o in e.open_orders()] == [at_limit]
+    close_day(e, "2024-01-02")
+    # Still the same day after its mark
+    e.place_market_order("A", "BUY", 1)
+    assert len(e.rejected_orders()) == 2
+    # The next day's first bar resets the baseline to the closes at 94
+    bar(e, "2024-01-03", 94)
+    allowed = e.place_market_order("A", "BUY", 1)
+    assert [o[0] for o in e.open_orders()] == [allowed]
+    assert len(e.rejected_orders()) == 2
+
+
+def test_daily_loss_limit_resets_without_end_of_day():
+    e = te.Engine(100_000, 0, 0, daily_loss_limit_paise=5_000)
+    e.place_market_order("A", "BUY", 1_000)
+    for date, px in [("2024-01-01", 100), ("2024-01-02", 96), ("2024-01-03", 92)]:
+        bar(e, date, px)
+        e.process_fills_for_date(date)
+        # Each day is only 4 paise a share down on the one before
+        e.place_market_order("A", "BUY", 1)
+    assert e.rejected_orders() == []
+    assert e.equity_curve() == []
+    # A deposit raises equity and the baseline alike, so it can't offset a loss
+    e.deposit("2024-01-03", 50_000)
+    bar(e, "2024-01-03", 86)
+    refused = e.place_market_order("A", "BUY", 1)
+    assert e.rejected_orders() == [(refused, "A", "DAILY_LOSS_LIMIT")]
+
+
+def test_drawdown_stop_liquidates_exactly_once():
+    e = te.Engine(100_000, 0, 0, max_drawdown_stop_pct=10.0)
+    e.place_market_order("A", "BUY", 1_000)
+    step(e, "2024-01-01", 100)
+    step(e, "2024-01-02", 95)
+    assert e.drawdown_stop_date() is None
+    resting = e.place_limit_order("A", "BUY", 100, 50)
+    step(e, "2024-01-03", 85)
+    assert e.drawdown_stop_date() == "2024-01-03"
+    assert [(o[1], o[2], o[3]) for o in e.open_orders()] == [("A", "SELL", 1_000.0)]
+    assert resting not in [o[0] for o in e.open_orders()]
+    step(e, "2024-01-04", 70)
+    step(e, "2024-01-05", 60)
+    assert e.position("A") == 0
+    assert [f[2] for f in e.fills()] == ["BUY", "SELL"]
+    assert (e.drawdown_stop_date(), e.open_orders()) == ("2024-01-03", [])
+    refused = e.place_market_order("A", "BUY", 1)
+    assert e.rejected_orders() == [(refused, "A", "DRAWDOWN_STOP")]
+
+
+def two_stopped_positions(**kw):
+    """Long 100 A at 1000 stopped at 950 and short 50 B at 2000 stopped at 2100."""
+    e = te.Engine(10_000_000, 0, 0, allow_short=True, **kw)
+    e.place_market_order("A", "BUY", 100)
+    e.place_market_order("B", "SELL", 50)
+    bar(e, "2024-01-01", 1_000, sym="A")
+    bar(e, "2024-01-01", 2_000, sym="B")
+    close_day(e, "2024-01-01")
+    e.place_stop_order("A", "SELL", 100, 950)
+    e.place_stop_order("B", "BUY", 50, 2_100)
+    return e
+
+
+def test_open_risk_sums_each_position_to_its_stop():
+    e = two_stopped_positions()
+    assert e.open_risk_paise() == 100 * 50 + 50 * 100
+
+
+def test_uncovered_qty_risks_the_default_percent():
+    e = two_stopped_positions(default_risk_pct=10.0)
+    assert e.open_risk_paise() == 10_000
+    # Another 100 A that no stop covers, at 10% of its cost
+    e.place_market_order("A", "BUY", 100)
+    bar(e, "2024-01-02", 1_000, sym="A")
+    bar(e, "2024-01-02", 2_000, sym="B")
+    close_day(e, "2024-01-02")
+    assert e.open_risk_paise() == 10_000 + 10_000
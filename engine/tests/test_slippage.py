@@ -0,0 +1,70 @@
+import trading_engine as te
+
+from helpers import step
+
+
+def fill_px(qty, **kw):
+    e = te.Engine(1_000_000_000, 0, 5, **kw)
+    e.place_market_order("A", "BUY", qty)
+    step(e, "2024-01-01", 10_000, vol=10_000)
+    return e.fills()[0][4]
+
+
+def test_large_order_slips_more_than_small_on_the_same_bar():
+    small, large = fill_px(10, impact_bps=100.0), fill_px(5_000, impact_bps=100.0)
+    assert small == 10_005  # 5 + 100 * 10 / 10000 = 5.1, rounded to 5 bps
+    assert large == 10_055  # 5 + 100 * 5000 / 10000 = 55 bps
+    assert large > small
+
+
+def test_zero_volume_bar_rejects_under_impact_slippage():
+    e = te.Engine(1_000_000, 0, 5, impact_bps=100.0)
+    oid = e.place_market_order("A", "BUY", 1)
+    step(e, "2024-01-01", 100, vol=0)
+    assert e.fills() == []
+    assert e.rejected_orders() == [(oid, "A", "ZERO_VOLUME")]
+
+
+def gap_fill(open_px):
+    e = te.Engine(1_000_000_000, 0, 0, gap_slippage_coeff=1.0, gap_threshold_bps=100)
+    step(e, "2024-01-01", 10_000)
+    e.place_market_order("A", "BUY", 1)
+    step(e, "2024-01-02", open_px)
+    return e.fills()[0][4]
+
+
+def test_gap_penalty_scales_with_the_gap():
+    # Gaps of 50, 300 and 500 bps over a 100 bps threshold: 0, 200 and 400 bps extra
+    assert gap_fill(10_050) == 10_050
+    assert gap_fill(10_300) == 10_300 + 10_300 * 200 // 10_000
+    assert gap_fill(10_500) == 10_500 + 10_500 * 400 // 10_000
+    assert gap_fill(9_500) == 9_500 + 9_500 * 400 // 10_000
+
+
+def test_fixed_slippage_adds_exactly_the_amount_per_share():
+    e = te.Engine(1_000_000, 0, 0, slippage_model={"type": "FIXED", "amount": 5})
+    e.place_market_order("A", "BUY", 100)
+    step(e, "2024-01-01", 37)
+    e.place_market_order("A", "SELL", 100)
+    step(e, "2024-01-02", 2_000)
+    buy, sell = e.fills()
+    assert (buy[4], sell[4]) == (42, 1_995)
+    assert (buy[9], sell[9]) == (500, 500)
+    assert e.metrics().slippage_paise == 1_000
+
+
+def test_tick_slippage_is_ticks_times_tick_size():
+    assert fill_px(10, slippage_model={"type": "TICKS", "ticks": 2, "amount": 5}) == 10_010
+
+
+def test_each_fill_records_its_slippage_cost():
+    e = te.Engine(100_000_000, 0, 10)
+    e.place_market_order("A", "BUY", 100)
+    step(e, "2024-01-01", 10_000)
+    e.place_market_order("A", "SELL", 50)
+    step(e, "2024-01-02", 20_000)
+    buy, sell = e.fills()
+    # 10 bps of each fill's unslipped notional
+    assert (buy[4], buy[9]) == (10_010, 10 * 100)
+    assert (sell[4], sell[9]) == (19_980, 20 * 50)
+    assert e.metrics().slippage_paise == buy[9] + sell[9]
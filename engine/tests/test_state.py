@@ -0,0 +1,129 @@
+import json
+
+import pytest
+import trading_engine as te
+
+from helpers import bar, close_day, step
+
+
+def config():
+    return dict(annual_cash_rate_bps=300, periods_per_year=12.0, cost_basis=te.CostBasis.Fifo, allow_short=True)
+
+
+def traded():
+    e = te.Engine(1_000_000, 5, 3, **config())
+    e.set_fee_bps_for_symbol("A", 7)
+    e.place_market_order("A", "BUY", 10)
+    step(e, "2024-01-01", 100)
+    e.place_market_order("A", "SELL", 25)
+    e.place_limit_order("A", "BUY", 5, 90)
+    step(e, "2024-01-02", 110)
+    return e
+
+
+def fresh():
+    e = te.Engine(1_000_000, 5, 3, **config())
+    e.set_fee_bps_for_symbol("A", 7)
+    return e
+
+
+def test_reset_matches_a_fresh_engine():
+    e = traded()
+    assert e.to_json() != fresh().to_json()
+    e.reset()
+    assert e.to_json() == fresh().to_json()
+
+
+def test_clone_config_matches_a_fresh_engine():
+    assert traded().clone_config().to_json() == fresh().to_json()
+
+
+def test_a_refused_mark_changes_nothing_and_can_be_retried():
+    calendar = ["2024-01-01", "2024-01-02", "2024-01-03"]
+    e = te.Engine(1_000_000, 0, 0, strict=True, trading_calendar=calendar, fill_missing_days=True,
+                  fill_timing=te.FillTiming.SameClose, annual_cash_rate_bps=500)
+    e.on_bar("2024-01-01", "A", 100, 100, 100, 100, 1_000)
+    e.place_market_order("A", "BUY", 10)
+    e.end_of_day("2024-01-01")
+    e.place_market_order("B", "BUY", 10)
+    # 2024-01-02 is skipped, and A has no bar on 2024-01-03 yet
+    bar(e, "2024-01-03", 200, sym="B")
+    before = e.to_json()
+    with pytest.raises(ValueError, match="open position in A has no bar"):
+        e.end_of_day("2024-01-03")
+    assert e.to_json() == before
+    bar(e, "2024-01-03", 110, sym="A")
+    e.end_of_day("2024-01-03")
+    assert e.missing_days() == ["2024-01-02"]
+    assert [d for d, _ in e.equity_curve()] == calendar
+    assert [(f[0], f[1]) for f in e.fills()] == [("2024-01-01", "A"), ("2024-01-03", "B")]
+
+
+def test_bar_callback_sees_fills_then_the_mark():
+    e = te.Engine(100_000, 0, 0, annual_cash_rate_bps=2_520)
+    seen = []
+    e.set_bar_callback(lambda stage, date, snapshot: seen.append((stage, date, snapshot)))
+    e.place_market_order("A", "BUY", 10)
+    step(e, "2024-01-01", 1_000, c=1_100)
+    # FILLS: after the fill, at the latest close; MARK: after a day's interest on 90,000
+    assert seen == [
+        ("FILLS", "2024-01-01", {"cash": 90_000, "equity": 101_000, "positions": {"A": 10.0}}),
+        ("MARK", "2024-01-01", {"cash": 90_090, "equity": 101_090, "positions": {"A": 10.0}}),
+    ]
+    assert e.equity_curve() == [("2024-01-01", 101_090)]
+
+
+def test_bar_callback_exception_propagates_after_the_mark():
+    e = te.Engine(100_000, 0, 0)
+
+    def fail(stage, date, snapshot):
+        raise RuntimeError(stage)
+
+    e.set_bar_callback(fail)
+    with pytest.raises(RuntimeError, match="MARK"):
+        e.end_of_day("2024-01-01")
+    assert e.equity_curve() == [("2024-01-01", 100_000)]
+    e.set_bar_callback(None)
+    e.end_of_day("2024-01-02")
+    assert len(e.equity_curve()) == 2
+
+
+def recorded_run():
+    e = te.Engine(1_000_000, 5, 3, record_calls=True, **config())
+    e.set_fee_bps_for_symbol("A", 7)
+    e.on_bars_batch([("2024-01-01", "A", 100, 105, 95, 102, 1_000_000), ("2024-01-01", "B", 50, 52, 49, 51, 1_000_000)])
+    e.order_target_percent("A", "BUY", 40)
+    e.place_limit_order("B", "BUY", 100, 50)
+    close_day(e, "2024-01-01")
+    e.deposit("2024-01-02", 200_000)
+    with pytest.raises(ValueError):
+        e.withdraw("2024-01-02", -5)
+    e.on_bars_batch([("2024-01-02", "A", 104, 110, 101, 108, 1_000_000), ("2024-01-02", "B", 50, 53, 48, 52, 1_000_000)])
+    e.order_target_notional("A", "SELL", 50_000)
+    e.place_market_order("B", "SELL", 40)
+    close_day(e, "2024-01-02")
+    step(e, "2024-01-03", 107)
+    return e
+
+
+def metric_values(e):
+    m = e.metrics()
+    return {k: getattr(m, k) for k in dir(m) if not k.startswith("_")}
+
+
+def test_replay_rebuilds_the_recorded_run():
+    e = recorded_run()
+    replayed = te.Engine.replay(e.call_log())
+    assert len(e.fills()) == 4
+    assert replayed.fills() == e.fills()
+    assert metric_values(replayed) == metric_values(e)
+    assert replayed.to_json() == e.to_json()
+
+
+def test_replay_raises_on_a_call_that_only_fails_on_replay():
+    log = json.loads(recorded_run().call_log())
+    failed = [call for call in log["calls"] if call[2] is not None]
+    assert [call[0] for call in failed] == ["withdraw"]
+    failed[0][2] = None
+    with pytest.raises(ValueError, match="withdraw raised"):
+        te.Engine.replay(json.dumps(log))